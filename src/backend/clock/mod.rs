@@ -0,0 +1,93 @@
+use chrono::{DateTime, Duration, FixedOffset, Local, NaiveDate, SecondsFormat};
+use std::sync::Mutex;
+
+/// Where every date and timestamp written to a clogfile comes from.
+///
+/// Dates are taken in the clock's own timezone, so "today" is whatever day it is
+/// where the clock says it is.
+pub trait Clock: Send + Sync {
+    /// the current time with its offset from UTC
+    fn now(&self) -> DateTime<FixedOffset>;
+
+    /// the current date in the clock's timezone
+    fn today(&self) -> NaiveDate {
+        self.now().date_naive()
+    }
+
+    /// the current time as RFC 3339 with nanoseconds and the offset, e.g.
+    /// `2025-05-25T23:59:30.123456789+02:00`
+    fn timestamp(&self) -> String {
+        self.now().to_rfc3339_opts(SecondsFormat::Nanos, false)
+    }
+}
+
+/// The local time of the machine.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> DateTime<FixedOffset> {
+        Local::now().fixed_offset()
+    }
+}
+
+/// A clock that only moves when told to, for tests and replays.
+#[derive(Debug)]
+pub struct FixedClock {
+    now: Mutex<DateTime<FixedOffset>>,
+}
+
+impl FixedClock {
+    pub fn new(now: DateTime<FixedOffset>) -> Self {
+        FixedClock {
+            now: Mutex::new(now),
+        }
+    }
+
+    /// moves the clock to `now`, which may be in another timezone
+    pub fn set(&self, now: DateTime<FixedOffset>) {
+        *self.now.lock().unwrap_or_else(|err| err.into_inner()) = now;
+    }
+
+    /// moves the clock forward by `duration`
+    pub fn advance(&self, duration: Duration) {
+        let mut now = self.now.lock().unwrap_or_else(|err| err.into_inner());
+        *now += duration;
+    }
+}
+
+impl Clock for FixedClock {
+    fn now(&self) -> DateTime<FixedOffset> {
+        *self.now.lock().unwrap_or_else(|err| err.into_inner())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn at(rfc3339: &str) -> DateTime<FixedOffset> {
+        DateTime::parse_from_rfc3339(rfc3339).unwrap()
+    }
+
+    #[test]
+    fn fixed_clock_moves_only_when_told() {
+        let clock = FixedClock::new(at("2025-05-25T23:59:59+00:00"));
+        assert_eq!(clock.today(), NaiveDate::from_ymd_opt(2025, 5, 25).unwrap());
+
+        clock.advance(Duration::seconds(1));
+        assert_eq!(clock.now(), at("2025-05-26T00:00:00+00:00"));
+        assert_eq!(clock.today(), NaiveDate::from_ymd_opt(2025, 5, 26).unwrap());
+    }
+
+    #[test]
+    fn today_follows_the_clock_timezone() {
+        // the same instant is still the 25th in New York but already the 26th in Tokyo
+        let clock = FixedClock::new(at("2025-05-25T22:30:00-04:00"));
+        assert_eq!(clock.today(), NaiveDate::from_ymd_opt(2025, 5, 25).unwrap());
+
+        clock.set(at("2025-05-26T11:30:00+09:00"));
+        assert_eq!(clock.now(), at("2025-05-25T22:30:00-04:00"));
+        assert_eq!(clock.today(), NaiveDate::from_ymd_opt(2025, 5, 26).unwrap());
+    }
+}
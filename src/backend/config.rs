@@ -1 +1,11 @@
-pub const CLOG_VERSION: &str = "1.0.1";
+/// version of the binary clogfile layout written by this crate
+pub const FORMAT_VERSION: u16 = 9;
+
+/// number of key slots, i.e. how many users can share one clogfile
+pub const KEY_SLOTS: usize = 8;
+
+/// longest user name a key slot can hold, in bytes
+pub const MAX_USER_NAME_LENGTH: usize = 32;
+
+/// user holding the first key slot of new and migrated clogfiles
+pub const DEFAULT_USER: &str = "owner";
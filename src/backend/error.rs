@@ -0,0 +1,151 @@
+use std::{fmt, io};
+
+/// Every error the public API can return.
+#[derive(Debug)]
+pub enum ClogError {
+    /// The password did not decrypt the metadata.
+    ///
+    /// AES-GCM cannot tell a wrong password from a tampered metadata ciphertext,
+    /// so both end up here.
+    WrongPassword,
+
+    /// No entry called `file` exists in `folder`.
+    NotFound { folder: String, file: String },
+
+    /// No folder called `folder` exists.
+    FolderNotFound { folder: String },
+
+    /// `file` in `folder` has no kept version numbered `version`.
+    VersionNotFound {
+        folder: String,
+        file: String,
+        version: u64,
+    },
+
+    /// An entry called `file` already exists in `folder`.
+    AlreadyExists { folder: String, file: String },
+
+    /// A folder called `folder` already exists.
+    FolderExists { folder: String },
+
+    /// `folder` still holds entries or folders.
+    FolderNotEmpty { folder: String },
+
+    /// The folder path can't be parsed, or names something that can't be a folder.
+    InvalidPath(String),
+
+    /// `folder` is sealed and its entries can no longer be changed.
+    ReadOnlyFolder { folder: String },
+
+    /// A user called `user` already has a key slot.
+    UserExists { user: String },
+
+    /// No key slot belongs to a user called `user`.
+    UserNotFound { user: String },
+
+    /// Every key slot is taken.
+    KeySlotsFull,
+
+    /// The user name or the requested user change is not allowed.
+    InvalidUser(String),
+
+    /// The key derivation parameters are out of range.
+    InvalidKdf(String),
+
+    /// The header section could not be parsed.
+    CorruptHeader(String),
+
+    /// The clogfile was written by a newer, unknown format version.
+    UnsupportedVersion(String),
+
+    /// The metadata section could not be read or decoded.
+    CorruptMetadata(String),
+
+    /// The metadata decrypted fine but is not valid metadata JSON.
+    InvalidMetadataJson(String),
+
+    /// The content of `file` in `folder` failed authentication, the entry is damaged.
+    CorruptEntry { folder: String, file: String },
+
+    /// Another process holds the clogfile lock and the lock policy gave up waiting.
+    Locked,
+
+    /// An underlying read or write on the clogfile failed.
+    Io(io::Error),
+}
+
+pub type Result<T> = std::result::Result<T, ClogError>;
+
+impl ClogError {
+    /// whether the clogfile itself is damaged, as opposed to a wrong password or a bad request
+    pub fn is_corruption(&self) -> bool {
+        matches!(
+            self,
+            ClogError::CorruptHeader(_)
+                | ClogError::CorruptMetadata(_)
+                | ClogError::InvalidMetadataJson(_)
+                | ClogError::CorruptEntry { .. }
+        )
+    }
+}
+
+impl fmt::Display for ClogError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ClogError::WrongPassword => write!(f, "wrong password"),
+            ClogError::NotFound { folder, file } => {
+                write!(f, "file '{}' not found in folder '{}'", file, folder)
+            }
+            ClogError::FolderNotFound { folder } => write!(f, "folder '{}' not found", folder),
+            ClogError::VersionNotFound {
+                folder,
+                file,
+                version,
+            } => write!(
+                f,
+                "file '{}' in folder '{}' has no version {}",
+                file, folder, version
+            ),
+            ClogError::AlreadyExists { folder, file } => {
+                write!(f, "file '{}' already exists in folder '{}'", file, folder)
+            }
+            ClogError::FolderExists { folder } => write!(f, "folder '{}' already exists", folder),
+            ClogError::FolderNotEmpty { folder } => write!(f, "folder '{}' is not empty", folder),
+            ClogError::InvalidPath(reason) => write!(f, "invalid path: {}", reason),
+            ClogError::ReadOnlyFolder { folder } => write!(f, "folder '{}' is read only", folder),
+            ClogError::UserExists { user } => write!(f, "user '{}' already exists", user),
+            ClogError::UserNotFound { user } => write!(f, "user '{}' not found", user),
+            ClogError::KeySlotsFull => write!(f, "every key slot is taken"),
+            ClogError::InvalidUser(reason) => write!(f, "invalid user: {}", reason),
+            ClogError::InvalidKdf(reason) => write!(f, "invalid key derivation: {}", reason),
+            ClogError::CorruptHeader(reason) => write!(f, "corrupt header: {}", reason),
+            ClogError::UnsupportedVersion(version) => write!(
+                f,
+                "clogfile format version {} is newer than this version of clog supports",
+                version
+            ),
+            ClogError::CorruptMetadata(reason) => write!(f, "corrupt metadata: {}", reason),
+            ClogError::InvalidMetadataJson(reason) => write!(f, "invalid metadata: {}", reason),
+            ClogError::CorruptEntry { folder, file } => {
+                write!(f, "file '{}' in folder '{}' is corrupt", file, folder)
+            }
+            ClogError::Locked => write!(f, "clogfile is locked by another process"),
+            ClogError::Io(err) => write!(f, "io error: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for ClogError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ClogError::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for ClogError {
+    fn from(err: io::Error) -> Self {
+        ClogError::Io(err)
+    }
+}
@@ -1,158 +1,150 @@
-use super::utils::{open_file_read, open_file_read_write};
+use super::utils::{open_file_read, open_file_read_write, write_bytes};
 use crate::backend::{
-    header::utils::{parse_header_from_file, update_metadata_offset_and_length_in_file},
-    metadata::metadata::Metadata,
+    clock::Clock,
+    error::{ClogError, Result},
+    header::utils::parse_header_from_file,
+    metadata::{file::Chunk, metadata::Metadata},
+    user::utils::generate_keys::generate_base64_nonce,
 };
 use aes_gcm::{Aes256Gcm, Key, KeyInit, Nonce, aead::Aead};
 use base64::{Engine, engine::general_purpose};
-use chrono::NaiveTime;
 use std::{
-    io::{Read, Seek, SeekFrom, Write},
-    isize,
+    io::{ErrorKind, Read, Seek, SeekFrom},
     path::PathBuf,
 };
 
-/// 1. Update nonce of file in metadata
-/// 2. Copy all content of files below given file
-/// 3. Update all offset of below file and metadata in metadata and header section
-/// 4. Write new edited content and paste old copied content
-/// 5. Write new updated metadata
+/// 1. Update nonce and modification time of file in metadata, keeping the old
+///    content as a version if the version history is on
+/// 2. Write the new content after everything else in the clogfile
+/// 3. Drop the versions past the retention limit
+/// 4. Write new updated metadata
+///
+/// Nothing already in the clogfile moves, the replaced content and whatever versions
+/// are dropped are left as dead bytes.
 pub fn edit_file_with_content(
     metadata: &mut Metadata,
-    password: &String,
+    base64_key: &str,
     foldername: &str,
     filename: &str,
     new_content: &str,
     clogfile_path: &PathBuf,
-) {
-    // 1. Update nonce of file in metadata
-
-    let folder = metadata.folders.get_mut(foldername).unwrap();
-
-    // update new nonce in metadata
-    folder.files.get_mut(filename).unwrap().update_nonce();
-
-    // get new parameters from file — clone/copy to avoid borrow conflicts
-    let (base64_key, base64_nonce, offset, old_length) = {
-        let (k, n, o, l) = folder.files.get(filename).unwrap().get_file_parameters();
-        (k.clone(), n.clone(), o, l) // clone key & nonce so borrow ends here
-    };
-
-    let base64_encrypted_content =
-        encrypt_and_encode_content_to_base64(new_content, &base64_key, &base64_nonce);
-
-    // update new length of file
-    folder
-        .files
-        .get_mut(filename)
-        .unwrap()
-        .update_length(base64_encrypted_content.len());
-
-    let delta_offset: isize = base64_encrypted_content.len() as isize - old_length as isize;
-
-    // 2. Copy all content of files given below file
-
-    let mut file = open_file_read_write(clogfile_path);
-
-    file.seek(SeekFrom::Start((offset + old_length).try_into().unwrap()))
-        .unwrap();
-    let mut below_file_content_bytes = Vec::new();
-    file.read_to_end(&mut below_file_content_bytes).unwrap();
-
-    // 3. Update all offset of below file and metadata
-
-    // update metadata offset in header section
-    update_metadata_offset_and_length_in_file(clogfile_path, delta_offset, 0);
-
-    // update all offset of below file
-
-    // import basefile time for comparison
-    let basefile_time = {
-        let base_created_at = &folder.files.get(filename).unwrap().created_at;
-        NaiveTime::parse_from_str(base_created_at, "%I:%M:%S %p").unwrap()
-    };
-
-    // check all the files in current folder and update all the offset
-    for (_, file_) in folder.files.iter_mut() {
-        let time = NaiveTime::parse_from_str(&file_.created_at, "%I:%M:%S %p").unwrap();
-        if time > basefile_time {
-            file_.update_offset(delta_offset);
-        }
+    clock: &dyn Clock,
+) -> Result<()> {
+    // 1. Update nonce and modification time of file in metadata
+    let version_limit = metadata.version_limit;
+    let file_ = metadata.get_file_mut(foldername, filename)?;
+    match version_limit {
+        0 => file_.chunks.clear(),
+        _ => file_.keep_version(),
     }
+    file_.update_nonce();
+    file_.touch(clock);
 
-    // 4. Write new edited content and paste old copied content
+    let (file_key, file_nonce, _, _) = file_.get_file_parameters();
+    let encrypted_content = encrypt_data(new_content.as_bytes(), file_key, file_nonce)?;
 
-    // write new content
-    file.seek(SeekFrom::Start(offset.try_into().unwrap()))
-        .unwrap();
-    file.write_all(base64_encrypted_content.as_bytes()).unwrap();
+    // 2. Write the new content after everything else
+    file_.offset = append_blob(&encrypted_content, clogfile_path)?;
+    file_.update_length(encrypted_content.len());
 
-    // write old content
-    file.seek(SeekFrom::Start(
-        (offset + base64_encrypted_content.len()) as u64,
-    ))
-    .unwrap();
-    file.write_all(&below_file_content_bytes).unwrap();
+    // 3. Drop the versions past the retention limit
+    file_.prune_versions(version_limit);
 
-    // 5. Write new updated metadata in file
+    // 4. Write new updated metadata in file
+    metadata.update_metadata_in_file(clogfile_path, base64_key)
+}
 
-    metadata.update_metadata_in_file(clogfile_path, password);
+/// 1. Removes file from local metadata
+/// 2. Writes new updated metadata
+///
+/// Every ciphertext the file had is dead bytes from now on.
+pub fn delete_file_with_content(
+    metadata: &mut Metadata,
+    base64_key: &str,
+    foldername: &str,
+    filename: &str,
+    clogfile_path: &PathBuf,
+) -> Result<()> {
+    // 1. Remove file from local metadata
+    metadata
+        .get_folder_mut(foldername)?
+        .files
+        .remove(filename)
+        .ok_or_else(|| ClogError::NotFound {
+            folder: foldername.to_string(),
+            file: filename.to_string(),
+        })?;
+
+    // 2. Write new updated metadata
+    metadata.update_metadata_in_file(clogfile_path, base64_key)
 }
 
 /// 1. Updates local metadata to include new file
 /// 2. Adds file content in the clogfile
-/// 3. Updates header section
-/// 4. Updates metadata section
+/// 3. Updates metadata section
 pub fn add_file_with_content(
     metadata: &mut Metadata,
-    password: &String,
+    base64_key: &str,
     foldername: &str,
     filename: &str,
     content: &str,
     clogfile_path: &PathBuf,
-) {
-    // get metadata offset from header
-    let (_, _, _, metadata_offset, _) = parse_header_from_file(clogfile_path);
-
-    // update local metadata to include new file
-    metadata.add_file(filename, foldername, metadata_offset, 0);
-
-    // get file parameters to encrypt the content
-    let (base64_key, base64_nonce, _, _) = metadata
-        .folders
-        .get(foldername)
-        .unwrap()
-        .files
-        .get(filename)
-        .unwrap()
-        .get_file_parameters();
+    clock: &dyn Clock,
+) -> Result<()> {
+    // update local metadata to include new file, it is placed once it is encrypted
+    metadata.add_file(filename, foldername, 0, 0, clock)?;
+    let file_ = metadata.get_file_mut(foldername, filename)?;
 
-    // encrypt and encode content to base64
-    let base64_encrypted_content =
-        encrypt_and_encode_content_to_base64(content, base64_key, base64_nonce);
-    let content_len = base64_encrypted_content.len();
+    // encrypt content
+    let (file_key, file_nonce, _, _) = file_.get_file_parameters();
+    let encrypted_content = encrypt_data(content.as_bytes(), file_key, file_nonce)?;
 
-    // update length of file in local metadata
-    metadata
-        .folders
-        .get_mut(foldername)
-        .unwrap()
-        .files
-        .get_mut(filename)
-        .unwrap()
-        .update_length(content_len);
+    // write file content in the clogfile and point the file at it
+    file_.offset = append_blob(&encrypted_content, clogfile_path)?;
+    file_.update_length(encrypted_content.len());
+
+    // update metadata in file
+    metadata.update_metadata_in_file(clogfile_path, base64_key)
+}
+
+/// 1. Encrypts the text with the key of the file under a fresh nonce
+/// 2. Writes it after everything else in the clogfile
+/// 3. Records it as a chunk of the file and writes new updated metadata
+pub fn append_to_file_with_content(
+    metadata: &mut Metadata,
+    base64_key: &str,
+    foldername: &str,
+    filename: &str,
+    text: &str,
+    clogfile_path: &PathBuf,
+    clock: &dyn Clock,
+) -> Result<()> {
+    // 1. Encrypt the text under a fresh nonce
+    let nonce = generate_base64_nonce();
+    let (file_key, _, _, _) = metadata
+        .get_file(foldername, filename)?
+        .get_file_parameters();
+    let encrypted_text = encrypt_data(text.as_bytes(), file_key, &nonce)?;
 
-    // update header section to update metadata_offset
-    update_metadata_offset_and_length_in_file(clogfile_path, content_len as isize, 0);
+    // 2. Write it after everything else
+    let offset = append_blob(&encrypted_text, clogfile_path)?;
 
-    // write file content in the clogfile
-    let mut file = open_file_read_write(clogfile_path);
-    file.seek(SeekFrom::Start(metadata_offset.try_into().unwrap()))
-        .unwrap();
-    file.write_all(base64_encrypted_content.as_bytes()).unwrap();
+    // 3. Record it as a chunk and write new updated metadata
+    metadata
+        .get_file_mut(foldername, filename)?
+        .push_chunk(Chunk::new(offset, encrypted_text.len(), nonce), clock);
+    metadata.update_metadata_in_file(clogfile_path, base64_key)
+}
 
-    // update metadata in file
-    metadata.update_metadata_in_file(clogfile_path, password);
+/// Writes a ciphertext at the end of the clogfile and gives the offset it was written at.
+///
+/// Nothing points at it until the metadata is written, so a crash before that only
+/// leaves dead bytes behind.
+fn append_blob(bytes: &[u8], clogfile_path: &PathBuf) -> Result<usize> {
+    let mut file = open_file_read_write(clogfile_path)?;
+    let offset = file.seek(SeekFrom::End(0))? as usize;
+    write_bytes(&mut file, bytes, "append ciphertext")?;
+    Ok(offset)
 }
 
 pub fn decrypt_content_from_file(
@@ -160,87 +152,271 @@ pub fn decrypt_content_from_file(
     foldername: &str,
     filename: &str,
     clogfile_path: &PathBuf,
-) -> String {
+) -> Result<String> {
+    let version = metadata.get_file(foldername, filename)?.version;
+    decrypt_version_from_file(metadata, foldername, filename, version, clogfile_path)
+}
+
+/// decrypts the content of a file numbered `version`, the current one or a kept one,
+/// followed by every chunk appended to it
+pub fn decrypt_version_from_file(
+    metadata: &Metadata,
+    foldername: &str,
+    filename: &str,
+    version: u64,
+    clogfile_path: &PathBuf,
+) -> Result<String> {
+    let header = parse_header_from_file(clogfile_path)?;
+
     // open file in read only mode
-    let mut file = open_file_read(clogfile_path);
+    let mut file = open_file_read(clogfile_path)?;
+
+    // get parameters of every ciphertext of the version to be decrypted
+    let (base64_key, pieces) = metadata
+        .get_file(foldername, filename)?
+        .get_version_parameters(version)
+        .ok_or_else(|| ClogError::VersionNotFound {
+            folder: foldername.to_string(),
+            file: filename.to_string(),
+            version,
+        })?;
+
+    // entries have their own key so a failure here means the entry is damaged
+    // rather than the password wrong
+    let corrupt_entry = || ClogError::CorruptEntry {
+        folder: foldername.to_string(),
+        file: filename.to_string(),
+    };
 
-    // get parameters of file to be decrypted
-    let (base64_key, base64_nonce, offset, length) = metadata
-        .folders
-        .get(foldername)
-        .unwrap()
-        .files
-        .get(filename)
-        .unwrap()
-        .get_file_parameters();
+    let mut content = Vec::new();
+    for (base64_nonce, offset, length) in pieces {
+        // get encrypted_content from file
+        file.seek(SeekFrom::Start(offset as u64))?;
 
-    // get base64_encrypted_content from file
-    file.seek(SeekFrom::Start(offset.try_into().unwrap()))
-        .unwrap();
+        // make a container to store bytes from file
+        let mut encrypted_content = vec![0u8; length];
 
-    // make a container to store bytes from file
-    let mut base64_encrypted_content = vec![0u8; length];
+        // read bytes from file and decode them to raw ciphertext
+        read_exact_or_corrupt(&mut file, &mut encrypted_content, "file content")?;
+        let ciphertext = header.decode_blob(encrypted_content)?;
 
-    // read bytes from file and convert bytes to string
-    file.read_exact(&mut base64_encrypted_content).unwrap();
-    let base64_encrypted_content = String::from_utf8(base64_encrypted_content).unwrap();
+        let plaintext =
+            decrypt_bytes(&ciphertext, base64_key, base64_nonce).map_err(|err| match err {
+                ClogError::WrongPassword => corrupt_entry(),
+                err => err,
+            })?;
+        content.extend_from_slice(&plaintext);
+    }
 
-    // decrypt base64_encrypted_content and return string
-    parse_base64_encrypted_data(&base64_encrypted_content, &base64_key, &base64_nonce)
+    String::from_utf8(content).map_err(|_| corrupt_entry())
 }
 
-pub fn parse_base64_encrypted_data(
-    base64_encrypted_data: &String,
-    base64_key: &String,
-    base64_nonce: &String,
-) -> String {
+/// decrypts raw AES-256-GCM ciphertext into a utf-8 string
+pub fn decrypt_data(ciphertext: &[u8], base64_key: &str, base64_nonce: &str) -> Result<String> {
+    let data_bytes = decrypt_bytes(ciphertext, base64_key, base64_nonce)?;
+
+    // Convert decrypted bytes to String
+    String::from_utf8(data_bytes)
+        .map_err(|_| ClogError::CorruptMetadata("decrypted data is not utf-8".to_string()))
+}
+
+/// decrypts raw AES-256-GCM ciphertext, failing authentication gives [`ClogError::WrongPassword`]
+pub fn decrypt_bytes(ciphertext: &[u8], base64_key: &str, base64_nonce: &str) -> Result<Vec<u8>> {
     // extract key from base64
-    let key_bytes = general_purpose::STANDARD.decode(base64_key).unwrap();
+    let key_bytes = decode_base64_exact(base64_key, "key", 32)?;
     let key = Key::<Aes256Gcm>::from_slice(&key_bytes);
 
     // extract nonce from base64
-    let nonce_bytes = general_purpose::STANDARD.decode(base64_nonce).unwrap();
+    let nonce_bytes = decode_base64_exact(base64_nonce, "nonce", 12)?;
     let nonce = Nonce::from_slice(&nonce_bytes);
 
-    // extract encrypted_metadata from base64
-    let ciphertext_bytes = general_purpose::STANDARD
-        .decode(base64_encrypted_data)
-        .unwrap();
-
     // make a cipher from key
     let cipher = Aes256Gcm::new(key);
 
-    // decrypt encrypted_metadata
-    let data_bytes = cipher.decrypt(&nonce, ciphertext_bytes.as_ref()).unwrap();
-
-    // Convert decrypted bytes to String
-    String::from_utf8(data_bytes).unwrap()
+    // decrypt ciphertext
+    cipher
+        .decrypt(nonce, ciphertext)
+        .map_err(|_| ClogError::WrongPassword)
 }
 
-fn encrypt_and_encode_content_to_base64(
-    content: &str,
-    base64_key: &str,
-    base64_nonce: &str,
-) -> String {
-    // make plaintext from content by converting it to bytes
-    let plaintext = content.as_bytes();
-
+/// encrypts plaintext into raw AES-256-GCM ciphertext
+pub fn encrypt_data(plaintext: &[u8], base64_key: &str, base64_nonce: &str) -> Result<Vec<u8>> {
     // decode key and make key for encryption
-    let key_bytes = general_purpose::STANDARD.decode(base64_key).unwrap();
+    let key_bytes = decode_base64_exact(base64_key, "key", 32)?;
     let key = Key::<Aes256Gcm>::from_slice(&key_bytes);
 
     // deocde nonce and make nonce for encryption
-    let nonce_bytes = general_purpose::STANDARD.decode(base64_nonce).unwrap();
+    let nonce_bytes = decode_base64_exact(base64_nonce, "nonce", 12)?;
     let nonce = Nonce::from_slice(&nonce_bytes);
 
     // make cipher
     let cipher = Aes256Gcm::new(key);
 
     // encrypt plaintext
-    let ciphertext = cipher.encrypt(nonce, plaintext).unwrap();
+    cipher
+        .encrypt(nonce, plaintext)
+        .map_err(|_| ClogError::CorruptMetadata("encryption failed".to_string()))
+}
 
-    // convert ciphertext
-    let base64_ciphertext = general_purpose::STANDARD.encode(&ciphertext);
+/// decodes a base64 key or nonce, rejecting it if it isn't `len` bytes long
+fn decode_base64_exact(data: &str, what: &str, len: usize) -> Result<Vec<u8>> {
+    general_purpose::STANDARD
+        .decode(data)
+        .ok()
+        .filter(|bytes| bytes.len() == len)
+        .ok_or_else(|| ClogError::CorruptMetadata(format!("invalid {}", what)))
+}
 
-    base64_ciphertext
+/// reads exactly `buf.len()` bytes, treating a short read as a truncated clogfile
+pub fn read_exact_or_corrupt(file: &mut impl Read, buf: &mut [u8], what: &str) -> Result<()> {
+    file.read_exact(buf).map_err(|err| match err.kind() {
+        ErrorKind::UnexpectedEof => {
+            ClogError::CorruptMetadata(format!("{} extends past end of file", what))
+        }
+        _ => ClogError::Io(err),
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use crate::backend::{
+        config::FORMAT_VERSION,
+        file_operations::utils::make_new_clogfile,
+        header::utils::{MAGIC, parse_header_from_file},
+        metadata::rollover::dated_folder_name,
+        session::{Clock, Clog, FixedClock},
+        test_utils::{TEST_KDF, make_test_clogfile, temp_clogfile},
+    };
+    use chrono::{DateTime, Duration};
+    use rand::{Rng, SeedableRng, rngs::StdRng};
+    use std::{collections::HashMap, sync::Arc};
+
+    #[test]
+    fn new_clogfiles_store_raw_ciphertext() {
+        let path = temp_clogfile("v2_layout");
+        make_test_clogfile("pw", &path);
+
+        let bytes = std::fs::read(&path).unwrap();
+        assert_eq!(&bytes[..MAGIC.len()], MAGIC);
+
+        let clog = Clog::open(&path, "pw").unwrap();
+        let header = parse_header_from_file(&path).unwrap();
+        assert_eq!(header.version, FORMAT_VERSION);
+
+        // "Welcome" content plus 16 byte tag, without any base64 inflation
+        let welcome = clog
+            .metadata()
+            .get_file(&clog.list().unwrap()[0].folder, "Welcome");
+        let content = clog
+            .read_file(&clog.list().unwrap()[0].folder, "Welcome")
+            .unwrap();
+        assert_eq!(welcome.unwrap().length, content.len() + 16);
+        assert_eq!(header.metadata_offset + header.metadata_length, bytes.len());
+    }
+
+    /// random lowercase text, possibly empty
+    fn random_content(rng: &mut StdRng) -> String {
+        let length = rng.random_range(0..300);
+        (0..length)
+            .map(|_| rng.random_range(b'a'..=b'z') as char)
+            .collect()
+    }
+
+    #[test]
+    fn random_adds_edits_and_appends_across_days_keep_every_entry_readable() {
+        for seed in 0..4 {
+            let mut rng = StdRng::seed_from_u64(seed);
+            let path = temp_clogfile("property_edits");
+            let start = DateTime::parse_from_rfc3339("2025-05-25T09:00:00+02:00").unwrap();
+            let clock = Arc::new(FixedClock::new(start));
+            make_new_clogfile("pw", &path, TEST_KDF, &*clock).unwrap();
+            let mut clog = Clog::open(&path, "pw").unwrap().with_clock(clock.clone());
+            // the first seed edits in place, the others keep a few versions around
+            let version_limit = seed as usize;
+            clog.set_version_limit(version_limit).unwrap();
+
+            let mut expected: HashMap<(String, String), String> = HashMap::new();
+            expected.insert(
+                (dated_folder_name(clock.today()), "Welcome".to_string()),
+                clog.read_file(&dated_folder_name(clock.today()), "Welcome")
+                    .unwrap(),
+            );
+            // every content an entry had, numbered from 1 like its versions
+            let mut history: HashMap<(String, String), Vec<String>> = expected
+                .iter()
+                .map(|(key, content)| (key.clone(), vec![content.clone()]))
+                .collect();
+
+            for step in 0..60 {
+                let content = random_content(&mut rng);
+                match rng.random_range(0..6) {
+                    0 => clock.advance(Duration::days(1)),
+                    1 | 2 => {
+                        let filename = format!("entry {}", step);
+                        clog.add_file(&filename, &content).unwrap();
+                        let key = (dated_folder_name(clock.today()), filename);
+                        history.insert(key.clone(), vec![content.clone()]);
+                        expected.insert(key, content);
+                    }
+                    3 => {
+                        // appending leaves everything in place, whatever is stored after
+                        let mut keys: Vec<_> = expected.keys().cloned().collect();
+                        keys.sort();
+                        let key = keys[rng.random_range(0..keys.len())].clone();
+                        clog.override_read_only("property test", |clog| {
+                            clog.append_to_file(&key.0, &key.1, &content)
+                        })
+                        .unwrap();
+                        history
+                            .get_mut(&key)
+                            .unwrap()
+                            .last_mut()
+                            .unwrap()
+                            .push_str(&content);
+                        expected.get_mut(&key).unwrap().push_str(&content);
+                    }
+                    _ => {
+                        // edit any entry, usually one that has later days stored after it,
+                        // past days only take edits through an override
+                        let mut keys: Vec<_> = expected.keys().cloned().collect();
+                        keys.sort();
+                        let key = keys[rng.random_range(0..keys.len())].clone();
+                        clog.override_read_only("property test", |clog| {
+                            clog.edit_file(&key.0, &key.1, &content)
+                        })
+                        .unwrap();
+                        history.get_mut(&key).unwrap().push(content.clone());
+                        expected.insert(key, content);
+                    }
+                }
+
+                for ((foldername, filename), content) in &expected {
+                    assert_eq!(
+                        &clog.read_file(foldername, filename).unwrap(),
+                        content,
+                        "seed {} step {}",
+                        seed,
+                        step
+                    );
+                }
+            }
+
+            let clog = Clog::open(&path, "pw").unwrap();
+            assert_eq!(clog.list().unwrap().len(), expected.len());
+            for ((foldername, filename), content) in &expected {
+                assert_eq!(&clog.read_file(foldername, filename).unwrap(), content);
+
+                let contents = &history[&(foldername.clone(), filename.clone())];
+                let versions = clog.list_versions(foldername, filename).unwrap();
+                assert_eq!(versions.len(), contents.len().min(version_limit + 1));
+                for version in versions {
+                    assert_eq!(
+                        clog.get_version(foldername, filename, version.number)
+                            .unwrap(),
+                        contents[version.number as usize - 1]
+                    );
+                }
+            }
+        }
+    }
 }
@@ -0,0 +1,191 @@
+use super::utils::sibling_path;
+use crate::backend::error::{ClogError, Result};
+use std::{
+    fs::{File, OpenOptions, TryLockError},
+    io::ErrorKind,
+    path::Path,
+    thread,
+    time::{Duration, Instant},
+};
+
+/// how long a timed lock waits between attempts
+const RETRY_INTERVAL: Duration = Duration::from_millis(10);
+
+/// What to do when another process already holds the clogfile lock.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum LockPolicy {
+    /// wait until the lock is released
+    #[default]
+    Block,
+    /// fail right away with [`ClogError::Locked`]
+    Try,
+    /// wait at most this long, then fail with [`ClogError::Locked`]
+    Timeout(Duration),
+}
+
+/// Advisory lock on a clogfile, released when dropped.
+///
+/// The lock lives on a `<name>.lock` sidecar instead of the clogfile itself,
+/// because key slot changes and compaction rename a new file over the clogfile.
+/// Readers of a clogfile where no sidecar can be created lock the clogfile instead.
+pub struct ClogLock {
+    _file: File,
+}
+
+/// shared lock for reading, any number of readers may hold it at once
+pub fn lock_shared(clogfile_path: &Path, policy: LockPolicy) -> Result<ClogLock> {
+    // a missing clogfile fails here, before a sidecar is left behind for it
+    let clogfile = File::open(clogfile_path)?;
+    let file = match open_sidecar(clogfile_path) {
+        Ok(file) => file,
+        // read-only directory or media: writers can't create the sidecar either,
+        // so no one else needs to see this lock
+        Err(err)
+            if matches!(
+                err.kind(),
+                ErrorKind::PermissionDenied | ErrorKind::ReadOnlyFilesystem
+            ) =>
+        {
+            clogfile
+        }
+        Err(err) => return Err(err.into()),
+    };
+    acquire(file, policy, File::lock_shared, File::try_lock_shared)
+}
+
+/// exclusive lock for writing, held by no one else at the same time
+pub fn lock_exclusive(clogfile_path: &Path, policy: LockPolicy) -> Result<ClogLock> {
+    acquire(
+        open_sidecar(clogfile_path)?,
+        policy,
+        File::lock,
+        File::try_lock,
+    )
+}
+
+/// opens the `<name>.lock` sidecar, creating it if needed
+fn open_sidecar(clogfile_path: &Path) -> std::io::Result<File> {
+    OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(sibling_path(clogfile_path, "lock"))
+}
+
+fn acquire(
+    file: File,
+    policy: LockPolicy,
+    lock: fn(&File) -> std::io::Result<()>,
+    try_lock: fn(&File) -> std::result::Result<(), TryLockError>,
+) -> Result<ClogLock> {
+    let deadline = match policy {
+        LockPolicy::Block => {
+            lock(&file)?;
+            return Ok(ClogLock { _file: file });
+        }
+        LockPolicy::Try => Instant::now(),
+        LockPolicy::Timeout(timeout) => Instant::now() + timeout,
+    };
+
+    loop {
+        match try_lock(&file) {
+            Ok(()) => return Ok(ClogLock { _file: file }),
+            Err(TryLockError::Error(err)) => return Err(err.into()),
+            Err(TryLockError::WouldBlock) if Instant::now() >= deadline => {
+                return Err(ClogError::Locked);
+            }
+            Err(TryLockError::WouldBlock) => thread::sleep(RETRY_INTERVAL),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::backend::test_utils::temp_clogfile;
+    use std::fs;
+
+    #[test]
+    fn readers_share_and_writers_exclude() {
+        let path = temp_clogfile("lock");
+        fs::write(&path, b"").unwrap();
+
+        let first = lock_shared(&path, LockPolicy::Try).unwrap();
+        let second = lock_shared(&path, LockPolicy::Try).unwrap();
+        assert!(matches!(
+            lock_exclusive(&path, LockPolicy::Try),
+            Err(ClogError::Locked)
+        ));
+
+        drop((first, second));
+        let writer = lock_exclusive(&path, LockPolicy::Try).unwrap();
+        assert!(matches!(
+            lock_shared(&path, LockPolicy::Try),
+            Err(ClogError::Locked)
+        ));
+        drop(writer);
+        lock_shared(&path, LockPolicy::Try).unwrap();
+    }
+
+    #[test]
+    fn timeout_gives_up_and_block_waits() {
+        let path = temp_clogfile("lock_wait");
+        fs::write(&path, b"").unwrap();
+        let writer = lock_exclusive(&path, LockPolicy::Block).unwrap();
+
+        let started = Instant::now();
+        assert!(matches!(
+            lock_shared(&path, LockPolicy::Timeout(Duration::from_millis(50))),
+            Err(ClogError::Locked)
+        ));
+        assert!(started.elapsed() >= Duration::from_millis(50));
+
+        let waiter = {
+            let path = path.clone();
+            thread::spawn(move || lock_exclusive(&path, LockPolicy::Block).map(|_| ()))
+        };
+        thread::sleep(Duration::from_millis(50));
+        assert!(!waiter.is_finished());
+        drop(writer);
+        waiter.join().unwrap().unwrap();
+    }
+
+    #[test]
+    fn reading_a_missing_clogfile_leaves_no_sidecar() {
+        let path = temp_clogfile("lock_missing");
+        let _ = fs::remove_file(sibling_path(&path, "lock"));
+
+        match lock_shared(&path, LockPolicy::Try) {
+            Err(ClogError::Io(err)) => assert_eq!(err.kind(), ErrorKind::NotFound),
+            _ => panic!("locked a missing clogfile"),
+        }
+        assert!(!sibling_path(&path, "lock").exists());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn readers_lock_the_clogfile_where_no_sidecar_fits() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = temp_clogfile("lock_read_only_dir");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir(&dir).unwrap();
+        let path = dir.join("journal.clog");
+        fs::write(&path, b"").unwrap();
+        fs::set_permissions(&dir, fs::Permissions::from_mode(0o555)).unwrap();
+
+        // root ignores the permissions, there is nothing to fall back from then
+        let writable = File::create(dir.join("probe")).is_ok();
+        if !writable {
+            let reader = lock_shared(&path, LockPolicy::Try).unwrap();
+            lock_shared(&path, LockPolicy::Try).unwrap();
+            assert!(lock_exclusive(&path, LockPolicy::Try).is_err());
+            assert!(!sibling_path(&path, "lock").exists());
+            drop(reader);
+        }
+
+        fs::set_permissions(&dir, fs::Permissions::from_mode(0o755)).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -1,2 +1,4 @@
 pub mod content;
+pub mod lock;
+pub mod rewrite;
 pub mod utils;
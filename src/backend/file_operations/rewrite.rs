@@ -0,0 +1,97 @@
+use super::{content::read_exact_or_corrupt, utils::open_file_read};
+use crate::backend::{
+    error::{ClogError, Result},
+    header::utils::{HEADER_LENGTH, Header},
+    metadata::{file::BlobId, metadata::Metadata},
+    user::utils::generate_keys::generate_base64_nonce,
+};
+use std::{
+    io::{Seek, SeekFrom},
+    path::PathBuf,
+};
+
+/// Raw ciphertext of one entry, addressed by folder and file name.
+pub struct EntryCiphertext {
+    pub foldername: String,
+    pub filename: String,
+    /// which of the entry's ciphertexts this is
+    pub blob: BlobId,
+    pub ciphertext: Vec<u8>,
+}
+
+/// Reads the raw ciphertext of every entry, kept version and appended chunk in
+/// `metadata`, in the order they are laid out in the clogfile.
+pub fn read_entry_ciphertexts(
+    clogfile_path: &PathBuf,
+    header: &Header,
+    metadata: &Metadata,
+) -> Result<Vec<EntryCiphertext>> {
+    let mut entries: Vec<(String, &String, BlobId, usize, usize)> = Vec::new();
+    for (foldername, folder) in metadata.all_folders() {
+        for (filename, file) in &folder.files {
+            for blob in file.blobs() {
+                if let Some((offset, length)) = file.blob(blob) {
+                    entries.push((foldername.clone(), filename, blob, offset, length));
+                }
+            }
+        }
+    }
+    entries.sort_by_key(|&(_, _, _, offset, _)| offset);
+
+    let mut file = open_file_read(clogfile_path)?;
+    let mut ciphertexts = Vec::with_capacity(entries.len());
+
+    for (foldername, filename, blob, offset, length) in entries {
+        file.seek(SeekFrom::Start(offset as u64))?;
+        let mut stored = vec![0u8; length];
+        read_exact_or_corrupt(&mut file, &mut stored, "file content")?;
+
+        ciphertexts.push(EntryCiphertext {
+            foldername,
+            filename: filename.clone(),
+            blob,
+            ciphertext: header.decode_blob(stored)?,
+        });
+    }
+
+    Ok(ciphertexts)
+}
+
+/// Builds a complete clogfile in the current format, with every entry packed
+/// right after the header and the metadata re-encrypted under a fresh nonce,
+/// preceded by its header snapshot.
+///
+/// `metadata` is updated to point at the new entry locations.
+pub fn build_clogfile(
+    header: &Header,
+    metadata: &mut Metadata,
+    entries: &[EntryCiphertext],
+    base64_key: &str,
+) -> Result<Vec<u8>> {
+    let mut body = Vec::new();
+
+    for entry in entries {
+        let file = metadata.get_file_mut(&entry.foldername, &entry.filename)?;
+        let (offset, length) = file.blob_mut(entry.blob).ok_or_else(|| {
+            ClogError::CorruptMetadata(format!(
+                "file '{}' in folder '{}' lost a ciphertext",
+                entry.filename, entry.foldername
+            ))
+        })?;
+        *offset = HEADER_LENGTH + body.len();
+        *length = entry.ciphertext.len();
+        body.extend_from_slice(&entry.ciphertext);
+    }
+
+    let mut header = header.clone();
+    header.base64_nonce = generate_base64_nonce();
+    let encrypted_metadata = metadata.to_encrypted_metadata(base64_key, &header)?;
+    header.metadata_offset = HEADER_LENGTH + body.len() + header.snapshot_length();
+    header.metadata_length = encrypted_metadata.len();
+
+    let mut bytes = header.to_bytes()?;
+    bytes.extend_from_slice(&body);
+    bytes.extend_from_slice(&header.to_bytes()?);
+    bytes.extend_from_slice(&encrypted_metadata);
+    Ok(bytes)
+}
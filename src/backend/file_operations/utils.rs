@@ -1,62 +1,174 @@
-use super::{super::metadata, content::add_file_with_content};
+use super::{
+    super::metadata,
+    content::add_file_with_content,
+    lock::{LockPolicy, lock_exclusive},
+};
 use crate::backend::{
-    config::CLOG_VERSION,
-    header::utils::{HEADER_LENGTH, init},
-    user::utils::generate_keys::{generate_base64_nonce, generate_base64_salt},
+    clock::Clock,
+    config::DEFAULT_USER,
+    error::{ClogError, Result},
+    header::utils::Header,
+    metadata::rollover::dated_folder_name,
+    user::{
+        key_slot::KeySlot,
+        utils::{
+            derive_key::Kdf,
+            generate_keys::{generate_base64_key, generate_base64_nonce},
+        },
+    },
 };
-use chrono::Local;
+use aes_gcm::aead::{OsRng, rand_core::RngCore};
 use std::{
-    fs::{File, OpenOptions},
-    io::{Seek, SeekFrom, Write},
-    path::PathBuf,
+    ffi::OsString,
+    fs::{self, File, OpenOptions},
+    io::{ErrorKind, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
 };
 
-fn write_from_offset(file: &mut File, content: &str, offset: usize) {
-    file.seek(SeekFrom::Start(offset.try_into().unwrap()))
-        .unwrap();
-    file.write_all(content.as_bytes()).unwrap();
+pub fn open_file_read_write(path: &PathBuf) -> Result<File> {
+    crash_point("open for writing")?;
+    let file = OpenOptions::new().read(true).write(true).open(path)?;
+    Ok(file)
+}
+
+pub fn open_file_read(path: &PathBuf) -> Result<File> {
+    let file = OpenOptions::new().read(true).open(path)?;
+    Ok(file)
+}
+
+/// Writes `bytes` at the current position of `file`.
+///
+/// A crash simulated at `step` leaves only the first half of `bytes` behind, like a
+/// write torn by a power cut.
+pub fn write_bytes(file: &mut File, bytes: &[u8], step: &str) -> Result<()> {
+    if let Err(err) = crash_point(step) {
+        file.write_all(&bytes[..bytes.len() / 2])?;
+        return Err(err);
+    }
+    file.write_all(bytes)?;
+    Ok(())
+}
+
+/// syncs what was written to `file`, unless a crash is simulated at `step` first
+pub fn sync_bytes(file: &File, step: &str) -> Result<()> {
+    crash_point(step)?;
+    file.sync_data()?;
+    Ok(())
+}
+
+/// gives a path next to the clogfile, e.g. `journal.clog` -> `journal.clog.bak`
+pub fn sibling_path(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = OsString::from(path.as_os_str());
+    name.push(".");
+    name.push(suffix);
+    PathBuf::from(name)
+}
+
+/// replaces the clogfile with `bytes` by writing a temporary sibling,
+/// syncing it to disk and renaming it over the original
+pub fn write_file_atomically(path: &PathBuf, bytes: &[u8]) -> Result<()> {
+    let work_path = sibling_path(path, "tmp");
+
+    crash_point("create working copy")?;
+    let mut work_file = File::create(&work_path)?;
+    work_file.write_all(bytes)?;
+    drop(work_file);
+
+    commit_working_copy(&work_path, path)
+}
+
+/// Runs `op` against a copy of the clogfile and renames the copy over the
+/// original only once `op` succeeded and the copy is synced to disk.
+///
+/// The original is never written in place, so a crash at any point leaves
+/// either the old or the new clogfile behind.
+pub fn with_working_copy<T>(
+    clogfile_path: &PathBuf,
+    op: impl FnOnce(&PathBuf) -> Result<T>,
+) -> Result<T> {
+    let work_path = sibling_path(clogfile_path, "tmp");
+
+    crash_point("create working copy")?;
+    fs::copy(clogfile_path, &work_path)?;
+
+    let value = op(&work_path).inspect_err(|_| {
+        let _ = fs::remove_file(&work_path);
+    })?;
+
+    commit_working_copy(&work_path, clogfile_path)?;
+    Ok(value)
+}
+
+/// syncs the working copy and atomically moves it over the clogfile
+fn commit_working_copy(work_path: &PathBuf, clogfile_path: &PathBuf) -> Result<()> {
+    crash_point("sync working copy")?;
+    open_file_read_write(work_path)?.sync_all()?;
+
+    crash_point("rename working copy")?;
+    fs::rename(work_path, clogfile_path)?;
+
+    crash_point("sync directory")?;
+    sync_parent_dir(clogfile_path)
 }
 
-pub fn open_file_read_write(path: &PathBuf) -> File {
-    let file = OpenOptions::new()
-        .read(true)
-        .write(true)
-        .create(true)
-        .open(path)
-        .unwrap();
-    file
+/// Overwrites `length` bytes at `offset` with random data and syncs them.
+///
+/// Used on the old clogfile after a working copy replaced it, so stale ciphertext
+/// doesn't linger on disk. Copy-on-write filesystems and SSD wear levelling may
+/// still keep older copies of those blocks.
+pub fn wipe_region(file: &mut File, offset: usize, length: usize) -> Result<()> {
+    let mut noise = vec![0u8; length];
+    OsRng.fill_bytes(&mut noise);
+
+    file.seek(SeekFrom::Start(offset as u64))?;
+    file.write_all(&noise)?;
+    file.sync_all()?;
+    Ok(())
 }
 
-pub fn open_file_read(path: &PathBuf) -> File {
-    let file = OpenOptions::new().read(true).open(path).unwrap();
-    file
+/// makes a rename inside the directory durable
+fn sync_parent_dir(path: &Path) -> Result<()> {
+    #[cfg(unix)]
+    {
+        let parent = match path.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent,
+            _ => Path::new("."),
+        };
+        File::open(parent)?.sync_all()?;
+    }
+    #[cfg(not(unix))]
+    let _ = path;
+    Ok(())
 }
 
-pub fn make_new_clogfile(password: &String, clogfile_path: &PathBuf) {
-    let mut metadata = metadata::init::init();
-    let base64_salt = generate_base64_salt();
+pub fn make_new_clogfile(
+    password: &str,
+    clogfile_path: &PathBuf,
+    kdf: Kdf,
+    clock: &dyn Clock,
+) -> Result<()> {
+    let mut metadata = metadata::init::init(clock);
     let base64_nonce = generate_base64_nonce();
 
-    // used 312 as temp metadata length
-    let (header_line1, header_line2) = init(
-        CLOG_VERSION,
-        312,
-        HEADER_LENGTH,
-        &base64_salt,
-        &base64_nonce,
-    );
+    // the metadata is encrypted with a random master key, the password only unlocks it
+    let base64_key = generate_base64_key();
+    let key_slot = KeySlot::new(DEFAULT_USER, password, kdf, &base64_key)?;
 
-    let mut file = open_file_read_write(clogfile_path);
+    let header = Header::new(&base64_nonce, vec![key_slot]);
 
-    // write header section
+    // never overwrite an existing clogfile, nor race another process creating one
+    let _lock = lock_exclusive(clogfile_path, LockPolicy::default())?;
+    if clogfile_path.exists() {
+        return Err(ClogError::Io(ErrorKind::AlreadyExists.into()));
+    }
 
-    file.write_all(header_line1.as_bytes()).unwrap();
-    file.write_all(b"\n").unwrap();
-    file.write_all(header_line2.as_bytes()).unwrap();
-    file.write_all(b"\n").unwrap();
+    // build the whole clogfile next to its final path
+    let work_path = sibling_path(clogfile_path, "tmp");
+    crash_point("create working copy")?;
+    File::create(&work_path)?.write_all(&header.to_bytes()?)?;
 
     // foldername
-    let foldername = Local::now().format("%d/%m/%Y").to_string();
+    let foldername = dated_folder_name(clock.today());
 
     // Welcome file parameteres
     let content =
@@ -65,10 +177,230 @@ pub fn make_new_clogfile(password: &String, clogfile_path: &PathBuf) {
     // write file content in file
     add_file_with_content(
         &mut metadata,
-        password,
+        &base64_key,
         &foldername,
         "Welcome",
         content,
-        clogfile_path,
-    );
+        &work_path,
+        clock,
+    )?;
+
+    commit_working_copy(&work_path, clogfile_path)
+}
+
+#[cfg(not(test))]
+fn crash_point(_step: &str) -> Result<()> {
+    Ok(())
+}
+
+#[cfg(test)]
+use simulated_crash::crash_point;
+
+/// Lets tests kill a write at any step, as if the process died right there.
+#[cfg(test)]
+pub mod simulated_crash {
+    use crate::backend::error::Result;
+    use std::cell::{Cell, RefCell};
+
+    thread_local! {
+        static STEPS_LEFT: Cell<Option<usize>> = const { Cell::new(None) };
+        static CRASHED_AT: RefCell<Option<String>> = const { RefCell::new(None) };
+    }
+
+    /// the next `steps` write steps succeed, the one after that crashes
+    pub fn arm(steps: usize) {
+        STEPS_LEFT.with(|left| left.set(Some(steps)));
+        CRASHED_AT.with(|crashed_at| crashed_at.replace(None));
+    }
+
+    /// stops crashing and gives the step the armed crash hit, if it was reached
+    pub fn disarm() -> Option<String> {
+        STEPS_LEFT.with(|left| left.set(None));
+        CRASHED_AT.with(|crashed_at| crashed_at.replace(None))
+    }
+
+    pub(super) fn crash_point(step: &str) -> Result<()> {
+        STEPS_LEFT.with(|left| match left.get() {
+            // once crashed, nothing else gets written
+            Some(0) => {
+                CRASHED_AT.with(|crashed_at| crashed_at.replace(Some(step.to_string())));
+                Err(std::io::Error::other(format!("simulated crash at {}", step)).into())
+            }
+            Some(n) => {
+                left.set(Some(n - 1));
+                Ok(())
+            }
+            None => Ok(()),
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::backend::{
+        clock::SystemClock,
+        recover::recover,
+        session::Clog,
+        test_utils::{TEST_KDF, make_v1_clogfile, temp_clogfile},
+    };
+
+    type Snapshot = Vec<(String, String, String)>;
+
+    /// every entry with its decrypted content
+    fn snapshot(path: &PathBuf) -> Result<Snapshot> {
+        let clog = Clog::open(path, "pw")?;
+        clog.list()?
+            .into_iter()
+            .map(|entry| {
+                let content = clog.read_file(&entry.folder, &entry.name)?;
+                Ok((entry.folder, entry.name, content))
+            })
+            .collect()
+    }
+
+    /// what a clogfile holds after a crash at `step`; if a torn metadata pointer keeps
+    /// it from opening, what [`recover`] salvages from its header snapshots
+    fn snapshot_after_crash(path: &PathBuf, step: &str) -> Snapshot {
+        match snapshot(path) {
+            Ok(state) => state,
+            Err(err) => {
+                assert_eq!(step, "write metadata pointer", "crash left {:?}", err);
+                assert!(err.is_corruption());
+                let recovered = sibling_path(path, "recovered");
+                let _ = fs::remove_file(&recovered);
+                recover(path, "pw", &recovered).unwrap();
+                let state = snapshot(&recovered).unwrap();
+                fs::remove_file(&recovered).unwrap();
+                state
+            }
+        }
+    }
+
+    /// Crashes `op` at every write step in turn and checks that the clogfile
+    /// then holds either the state from before or the state from after `op`.
+    fn assert_crash_safe(setup: impl Fn(&PathBuf), op: impl Fn(&PathBuf) -> Result<()>) {
+        let path = temp_clogfile("crash");
+
+        // run once without crashing to learn the expected end state
+        setup(&path);
+        let before = snapshot(&path).unwrap();
+        op(&path).unwrap();
+        let after = snapshot(&path).unwrap();
+        assert_ne!(before, after);
+
+        for step in 0.. {
+            let _ = fs::remove_file(&path);
+            setup(&path);
+
+            simulated_crash::arm(step);
+            let result = op(&path);
+            let Some(crashed_at) = simulated_crash::disarm() else {
+                result.unwrap();
+                assert_eq!(snapshot(&path).unwrap(), after);
+                break;
+            };
+
+            assert!(result.is_err());
+            let state = snapshot_after_crash(&path, &crashed_at);
+            assert!(
+                state == before || state == after,
+                "crash at step {} left an unexpected state",
+                step
+            );
+        }
+    }
+
+    fn new_clogfile(path: &PathBuf) {
+        make_new_clogfile("pw", path, TEST_KDF, &SystemClock).unwrap();
+    }
+
+    #[test]
+    fn add_file_survives_a_crash_at_every_step() {
+        assert_crash_safe(new_clogfile, |path| {
+            Clog::open(path, "pw")?.add_file("entry", "some content")
+        });
+    }
+
+    #[test]
+    fn edit_file_survives_a_crash_at_every_step() {
+        assert_crash_safe(new_clogfile, |path| {
+            let mut clog = Clog::open(path, "pw")?;
+            let folder = clog.list().unwrap()[0].folder.clone();
+            clog.edit_file(&folder, "Welcome", "rewritten")
+        });
+    }
+
+    #[test]
+    fn migration_survives_a_crash_at_every_step() {
+        assert_crash_safe(
+            |path| make_v1_clogfile("pw", path),
+            |path| {
+                let mut clog = Clog::open(path, "pw")?;
+                let folder = clog.list().unwrap()[0].folder.clone();
+                clog.edit_file(&folder, "Welcome", "rewritten after migration")
+            },
+        );
+    }
+
+    #[test]
+    fn torn_trailing_record_is_skipped() {
+        use crate::backend::metadata::metadata::Metadata;
+
+        let path = temp_clogfile("crash_torn_record");
+        new_clogfile(&path);
+        let before = snapshot(&path).unwrap();
+        let clean_length = fs::metadata(&path).unwrap().len();
+
+        // tear the record of a commit before the header points at it, without the
+        // cleanup a session would run, as after the process died
+        let (_, base64_key) = Metadata::unlock_key_from_file(&path, "pw").unwrap();
+        let mut metadata = Metadata::extract_metadata_with_key(&path, &base64_key).unwrap();
+        metadata.version_limit = 3;
+        simulated_crash::arm(1);
+        assert!(
+            metadata
+                .update_metadata_in_file(&path, &base64_key)
+                .is_err()
+        );
+        assert_eq!(
+            simulated_crash::disarm().as_deref(),
+            Some("append metadata record")
+        );
+        assert!(fs::metadata(&path).unwrap().len() > clean_length);
+
+        assert_eq!(snapshot(&path).unwrap(), before);
+        let recovered = sibling_path(&path, "recovered");
+        let _ = fs::remove_file(&recovered);
+        recover(&path, "pw", &recovered).unwrap();
+        assert_eq!(snapshot(&recovered).unwrap(), before);
+        fs::remove_file(&recovered).unwrap();
+
+        // the next commit goes after the torn bytes, which compaction drops
+        let mut clog = Clog::open(&path, "pw").unwrap();
+        clog.add_file("after the crash", "fine").unwrap();
+        assert_eq!(clog.version_limit().unwrap(), 0);
+        assert!(clog.compact().unwrap() > 0);
+        assert_eq!(snapshot(&path).unwrap().len(), before.len() + 1);
+    }
+
+    #[test]
+    fn new_clogfile_is_all_or_nothing() {
+        let path = temp_clogfile("crash_new");
+
+        for step in 0.. {
+            let _ = fs::remove_file(&path);
+            simulated_crash::arm(step);
+            let result = make_new_clogfile("pw", &path, TEST_KDF, &SystemClock);
+            if simulated_crash::disarm().is_none() {
+                result.unwrap();
+                break;
+            }
+
+            assert!(result.is_err());
+            assert!(!path.exists() || snapshot(&path).is_ok());
+        }
+
+        assert_eq!(snapshot(&path).unwrap().len(), 1);
+    }
 }
@@ -1,97 +1,556 @@
 use crate::backend::{
-    file_operations::utils::{open_file_read, open_file_read_write},
-    user::utils::generate_keys::generate_base64_nonce,
+    config::{FORMAT_VERSION, KEY_SLOTS, MAX_USER_NAME_LENGTH},
+    error::{ClogError, Result},
+    file_operations::utils::{open_file_read, open_file_read_write, sync_bytes, write_bytes},
+    user::{
+        key_slot::KeySlot,
+        utils::derive_key::{Kdf, LEGACY_KDF},
+    },
 };
+use base64::{Engine, engine::general_purpose};
 use std::{
-    io::{BufRead, BufReader, Seek, SeekFrom, Write},
+    io::{BufRead, BufReader, Read, Seek, SeekFrom},
+    ops::Range,
     path::PathBuf,
 };
 
-pub const HEADER_LENGTH: usize = 72;
-
-/// initiate header string for the first time when user is created
-///
-/// # Returns :
-///
-/// (header_line1,header_line2)
-///
-
-pub fn init(
-    version_id: &str,
-    metadata_length: usize,
-    metadata_offset: usize,
-    base64_salt: &str,
-    base64_nonce: &str,
-) -> (String, String) {
-    let line1 = format!("clog @{}", version_id);
-    let line2 = format!(
-        "{}.{}.{:08}.{:08}",
-        base64_salt, base64_nonce, metadata_length, metadata_offset
-    );
-    (line1, line2)
+/// first bytes of every binary (v2 onwards) clogfile
+pub const MAGIC: &[u8; 8] = b"\x89CLOG\r\n\x1a";
+
+/// length of one key slot:
+/// user name length u8, 0 for a free slot (1) | user name, zero padded (32)
+/// | kdf (13) | salt (16) | nonce (12) | wrapped master key (48)
+const KEY_SLOT_LENGTH: usize = 1 + MAX_USER_NAME_LENGTH + KDF_LENGTH + 16 + 12 + WRAPPED_KEY_LENGTH;
+
+/// v3 key slots had no kdf, every slot used PBKDF2
+const V3_KEY_SLOT_LENGTH: usize = KEY_SLOT_LENGTH - KDF_LENGTH;
+
+/// kdf id u8 (1 pbkdf2, 2 scrypt, 3 argon2id) | three u32 cost parameters:
+/// pbkdf2 iterations, 0, 0 | scrypt log n, r, p | argon2id memory in KiB, iterations, parallelism
+const KDF_LENGTH: usize = 13;
+
+/// 32 byte master key plus 16 byte tag
+const WRAPPED_KEY_LENGTH: usize = 48;
+
+/// length of the v4 header:
+/// magic (8) | format version u16 (2) | metadata nonce (12)
+/// | metadata offset u64 (8) | metadata length u64 (8) | key slots (8 * 122)
+pub const HEADER_LENGTH: usize = 38 + KEY_SLOTS * KEY_SLOT_LENGTH;
+
+/// metadata nonce, offset and length, the only bytes a commit rewrites in place;
+/// they sit well inside the first 512 byte sector, which disks write atomically
+pub const METADATA_POINTER: Range<usize> = 10..38;
+
+/// length of the v3 header, key slots (8 * 109) without kdf
+const V3_HEADER_LENGTH: usize = 38 + KEY_SLOTS * V3_KEY_SLOT_LENGTH;
+
+/// length of the v2 header:
+/// magic (8) | format version u16 (2) | salt (16) | metadata nonce (12)
+/// | metadata offset u64 (8) | metadata length u64 (8)
+const V2_HEADER_LENGTH: usize = 54;
+
+#[derive(Clone, Debug)]
+pub struct Header {
+    /// layout version of the clogfile, 1 for the legacy ASCII header
+    pub version: u16,
+    /// one slot per user, v1/v2 clogfiles have a single legacy slot
+    pub key_slots: Vec<KeySlot>,
+    pub base64_nonce: String,
+    pub metadata_length: usize,
+    pub metadata_offset: usize,
 }
 
-/// parses header section to give useful information
-///
-/// # Returns :
-///
-/// (base64_salt,base64_nonce,metadata_length,metadata_offset,version_id)
+impl Header {
+    /// initiate header for the first time when user is created,
+    /// metadata starts right after the header
+    pub fn new(base64_nonce: &str, key_slots: Vec<KeySlot>) -> Self {
+        Header {
+            version: FORMAT_VERSION,
+            key_slots,
+            base64_nonce: base64_nonce.to_string(),
+            metadata_length: 0,
+            metadata_offset: HEADER_LENGTH,
+        }
+    }
+
+    /// serializes header in the current binary layout
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        if self.version != FORMAT_VERSION {
+            return Err(ClogError::CorruptHeader(format!(
+                "v{} clogfile must be migrated before it is written",
+                self.version
+            )));
+        }
+        if self.key_slots.len() > KEY_SLOTS {
+            return Err(ClogError::KeySlotsFull);
+        }
+
+        let nonce = decode_exact(&self.base64_nonce, "metadata nonce", 12)?;
+
+        let mut bytes = Vec::with_capacity(HEADER_LENGTH);
+        bytes.extend_from_slice(MAGIC);
+        bytes.extend_from_slice(&FORMAT_VERSION.to_le_bytes());
+        bytes.extend_from_slice(&nonce);
+        bytes.extend_from_slice(&(self.metadata_offset as u64).to_le_bytes());
+        bytes.extend_from_slice(&(self.metadata_length as u64).to_le_bytes());
+
+        for slot in &self.key_slots {
+            if slot.is_legacy() || slot.wrapped_key.len() != WRAPPED_KEY_LENGTH {
+                return Err(ClogError::CorruptHeader("invalid key slot".to_string()));
+            }
+            if slot.user.is_empty() || slot.user.len() > MAX_USER_NAME_LENGTH {
+                return Err(ClogError::InvalidUser(slot.user.clone()));
+            }
+
+            let mut user = [0u8; MAX_USER_NAME_LENGTH];
+            user[..slot.user.len()].copy_from_slice(slot.user.as_bytes());
+            bytes.push(slot.user.len() as u8);
+            bytes.extend_from_slice(&user);
+            bytes.extend_from_slice(&encode_kdf(slot.kdf));
+            bytes.extend_from_slice(&decode_exact(&slot.base64_salt, "salt", 16)?);
+            bytes.extend_from_slice(&decode_exact(&slot.base64_nonce, "key slot nonce", 12)?);
+            bytes.extend_from_slice(&slot.wrapped_key);
+        }
+
+        // free slots are all zeroes
+        bytes.resize(HEADER_LENGTH, 0);
+        Ok(bytes)
+    }
+
+    /// how many bytes the header takes up, unknown for the variable length v1 header
+    pub fn length(&self) -> Option<usize> {
+        match self.version {
+            1 => None,
+            2 => Some(V2_HEADER_LENGTH),
+            3 => Some(V3_HEADER_LENGTH),
+            _ => Some(HEADER_LENGTH),
+        }
+    }
 
-pub fn parse_header_from_file(clogfile_path: &PathBuf) -> (String, String, usize, usize, String) {
-    let file = open_file_read(clogfile_path);
+    /// how many bytes of header snapshot precede the metadata record, from v9 on every
+    /// record comes right after a copy of the header pointing at it
+    pub fn snapshot_length(&self) -> usize {
+        match self.version {
+            ..=8 => 0,
+            _ => HEADER_LENGTH,
+        }
+    }
 
-    let mut header_line1 = String::new();
-    let mut header_line2 = String::new();
-    let buf_reader = BufReader::new(&file);
+    /// turns bytes stored in the clogfile back into raw ciphertext,
+    /// v1 clogfiles stored everything base64 encoded
+    pub fn decode_blob(&self, stored: Vec<u8>) -> Result<Vec<u8>> {
+        match self.version {
+            1 => general_purpose::STANDARD
+                .decode(stored)
+                .map_err(|_| ClogError::CorruptMetadata("stored data is not base64".to_string())),
+            _ => Ok(stored),
+        }
+    }
+}
+
+/// parses header section of either format to give useful information
+pub fn parse_header_from_file(clogfile_path: &PathBuf) -> Result<Header> {
+    let mut file = open_file_read(clogfile_path)?;
+
+    let mut magic = [0u8; 8];
+    let read = read_up_to(&mut file, &mut magic)?;
+    file.seek(SeekFrom::Start(0))?;
+
+    if read == magic.len() && &magic == MAGIC {
+        let mut bytes = vec![0u8; HEADER_LENGTH];
+        let read = read_up_to(&mut file, &mut bytes)?;
+        parse_header_from_bytes(&bytes[..read])
+    } else {
+        parse_v1_header(&file)
+    }
+}
+
+fn parse_v1_header(file: &std::fs::File) -> Result<Header> {
+    let buf_reader = BufReader::new(file);
     let mut line_reader = buf_reader.lines();
 
-    header_line1 = line_reader.next().unwrap().unwrap();
-    header_line2 = line_reader.next().unwrap().unwrap();
+    let header_line1 = line_reader
+        .next()
+        .ok_or_else(|| ClogError::CorruptHeader("missing version line".to_string()))?
+        .map_err(|_| ClogError::CorruptHeader("not a clog file".to_string()))?;
+    let header_line2 = line_reader
+        .next()
+        .ok_or_else(|| ClogError::CorruptHeader("missing parameter line".to_string()))?
+        .map_err(|_| ClogError::CorruptHeader("unreadable parameter line".to_string()))?;
+
+    let version_id = header_line1
+        .trim()
+        .strip_prefix("clog @")
+        .ok_or_else(|| ClogError::CorruptHeader("not a clog file".to_string()))?;
+
+    // only 1.x releases wrote ASCII headers
+    if version_id.split('.').next() != Some("1") {
+        return Err(ClogError::UnsupportedVersion(version_id.to_string()));
+    }
+    let array: Vec<&str> = header_line2.trim().split('.').collect();
+
+    if array.len() != 4 {
+        return Err(ClogError::CorruptHeader(format!(
+            "expected 4 header fields, found {}",
+            array.len()
+        )));
+    }
+
+    let parse_number = |field: &str, name: &str| {
+        field
+            .parse::<usize>()
+            .map_err(|_| ClogError::CorruptHeader(format!("invalid {}", name)))
+    };
+
+    Ok(Header {
+        version: 1,
+        key_slots: vec![KeySlot::legacy(array[0])],
+        base64_nonce: array[1].to_string(),
+        metadata_length: parse_number(array[2], "metadata length")?,
+        metadata_offset: parse_number(array[3], "metadata offset")?,
+    })
+}
+
+/// parses a binary (v2 onwards) header from the start of `bytes`,
+/// anything past the header is ignored
+pub fn parse_header_from_bytes(bytes: &[u8]) -> Result<Header> {
+    if !bytes.starts_with(MAGIC) {
+        return Err(ClogError::CorruptHeader("not a clog file".to_string()));
+    }
+    let read = bytes.len();
+    if read < 10 {
+        return Err(ClogError::CorruptHeader("header is truncated".to_string()));
+    }
+
+    let version = u16::from_le_bytes([bytes[8], bytes[9]]);
+    let (header_length, slot_length) = match version {
+        2 => (V2_HEADER_LENGTH, 0),
+        3 => (V3_HEADER_LENGTH, V3_KEY_SLOT_LENGTH),
+        // v5 to v8 only changed the metadata, v9 what precedes it
+        4..=FORMAT_VERSION => (HEADER_LENGTH, KEY_SLOT_LENGTH),
+        version if version > FORMAT_VERSION => {
+            return Err(ClogError::UnsupportedVersion(version.to_string()));
+        }
+        version => {
+            return Err(ClogError::CorruptHeader(format!(
+                "invalid format version {}",
+                version
+            )));
+        }
+    };
+    if read < header_length {
+        return Err(ClogError::CorruptHeader("header is truncated".to_string()));
+    }
+
+    let base64 = |at: usize, len: usize| general_purpose::STANDARD.encode(&bytes[at..at + len]);
+    let read_u64 = |at: usize| {
+        let mut field = [0u8; 8];
+        field.copy_from_slice(&bytes[at..at + 8]);
+        usize::try_from(u64::from_le_bytes(field))
+            .map_err(|_| ClogError::CorruptHeader("offset does not fit in memory".to_string()))
+    };
 
-    let (_, version_id) = header_line1.trim().split_at(6);
-    let array: Vec<&str> = header_line2.trim().split(".").collect();
+    if version == 2 {
+        return Ok(Header {
+            version,
+            key_slots: vec![KeySlot::legacy(&base64(10, 16))],
+            base64_nonce: base64(26, 12),
+            metadata_offset: read_u64(38)?,
+            metadata_length: read_u64(46)?,
+        });
+    }
 
-    (
-        array[0].to_string(),
-        array[1].to_string(),
-        array[2].parse().unwrap(),
-        array[3].parse().unwrap(),
-        version_id.to_string(),
-    )
+    let mut key_slots = Vec::new();
+    for slot in bytes[38..header_length].chunks_exact(slot_length) {
+        let user_length = slot[0] as usize;
+        if user_length == 0 {
+            continue;
+        }
+        let user = slot
+            .get(1..1 + user_length)
+            .filter(|_| user_length <= MAX_USER_NAME_LENGTH)
+            .and_then(|user| std::str::from_utf8(user).ok())
+            .ok_or_else(|| ClogError::CorruptHeader("invalid user name".to_string()))?;
+
+        let mut at = 1 + MAX_USER_NAME_LENGTH;
+        let kdf = if version == 3 {
+            LEGACY_KDF
+        } else {
+            at += KDF_LENGTH;
+            decode_kdf(&slot[at - KDF_LENGTH..at])?
+        };
+
+        key_slots.push(KeySlot {
+            user: user.to_string(),
+            kdf,
+            base64_salt: general_purpose::STANDARD.encode(&slot[at..at + 16]),
+            base64_nonce: general_purpose::STANDARD.encode(&slot[at + 16..at + 28]),
+            wrapped_key: slot[at + 28..].to_vec(),
+        });
+    }
+
+    Ok(Header {
+        version,
+        key_slots,
+        base64_nonce: base64(10, 12),
+        metadata_offset: read_u64(22)?,
+        metadata_length: read_u64(30)?,
+    })
+}
+
+/// offsets of every header in `bytes`, the one at the start and the snapshots preceding
+/// metadata records, along with anything else that merely starts with the magic number
+pub fn find_headers(bytes: &[u8]) -> Vec<usize> {
+    bytes
+        .windows(MAGIC.len())
+        .enumerate()
+        .filter(|(_, window)| window == MAGIC)
+        .map(|(offset, _)| offset)
+        .collect()
+}
+
+/// Points the header at the metadata record described by `header`, rewriting only the
+/// metadata nonce, offset and length in place and syncing them.
+pub fn write_metadata_pointer(clogfile_path: &PathBuf, header: &Header) -> Result<()> {
+    let bytes = header.to_bytes()?;
+    let mut file = open_file_read_write(clogfile_path)?;
+    file.seek(SeekFrom::Start(METADATA_POINTER.start as u64))?;
+    write_bytes(
+        &mut file,
+        &bytes[METADATA_POINTER],
+        "write metadata pointer",
+    )?;
+    sync_bytes(&file, "sync metadata pointer")
 }
 
-pub fn update_metadata_offset_and_length_in_file(
-    clogfile_path: &PathBuf,
-    delta_offset: isize,
-    length: usize,
-) {
-    let mut file = open_file_read_write(clogfile_path);
-
-    let (base64_salt, base64_nonce, mut metadata_length, mut metadata_offset, _) =
-        parse_header_from_file(clogfile_path);
-    metadata_length = length;
-    metadata_offset = (metadata_offset as isize + delta_offset) as usize;
-    let header_line2 = format!(
-        "{}.{}.{:08}.{:08}\n",
-        base64_salt, base64_nonce, metadata_length, metadata_offset
-    );
-    file.seek(SeekFrom::Start(12)).unwrap();
-    file.write_all(header_line2.as_bytes()).unwrap();
+fn encode_kdf(kdf: Kdf) -> [u8; KDF_LENGTH] {
+    let (id, params) = match kdf {
+        Kdf::Pbkdf2 { iterations } => (1, [iterations, 0, 0]),
+        Kdf::Scrypt { log_n, r, p } => (2, [log_n as u32, r, p]),
+        Kdf::Argon2id {
+            memory_kib,
+            iterations,
+            parallelism,
+        } => (3, [memory_kib, iterations, parallelism]),
+    };
+
+    let mut bytes = [0u8; KDF_LENGTH];
+    bytes[0] = id;
+    for (field, param) in bytes[1..].chunks_exact_mut(4).zip(params) {
+        field.copy_from_slice(&param.to_le_bytes());
+    }
+    bytes
 }
 
-pub fn update_nonce_in_file(clogfile_path: &PathBuf) {
-    let mut file = open_file_read_write(clogfile_path);
+fn decode_kdf(bytes: &[u8]) -> Result<Kdf> {
+    let param = |index: usize| {
+        let at = 1 + index * 4;
+        u32::from_le_bytes([bytes[at], bytes[at + 1], bytes[at + 2], bytes[at + 3]])
+    };
 
-    let (base64_salt, mut base64_nonce, metadata_length, metadata_offset, _) =
-        parse_header_from_file(clogfile_path);
-    base64_nonce = generate_base64_nonce();
-    let header_line2 = format!(
-        "{}.{}.{:08}.{:08}\n",
-        base64_salt, base64_nonce, metadata_length, metadata_offset
-    );
+    let kdf = match bytes[0] {
+        1 => Ok(Kdf::Pbkdf2 {
+            iterations: param(0),
+        }),
+        2 => Ok(Kdf::Scrypt {
+            log_n: u8::try_from(param(0))
+                .map_err(|_| ClogError::CorruptHeader("invalid scrypt cost".to_string()))?,
+            r: param(1),
+            p: param(2),
+        }),
+        3 => Ok(Kdf::Argon2id {
+            memory_kib: param(0),
+            iterations: param(1),
+            parallelism: param(2),
+        }),
+        id => Err(ClogError::CorruptHeader(format!("unknown kdf {}", id))),
+    }?;
 
-    file.seek(SeekFrom::Start(12)).unwrap();
-    file.write_all(header_line2.as_bytes()).unwrap();
+    // a flipped bit in the cost parameters must not make opening the file exhaust memory
+    kdf.check_cost()
+        .map_err(|err| ClogError::CorruptHeader(err.to_string()))?;
+    Ok(kdf)
 }
+
+fn decode_exact(data: &str, what: &str, len: usize) -> Result<Vec<u8>> {
+    general_purpose::STANDARD
+        .decode(data)
+        .ok()
+        .filter(|bytes| bytes.len() == len)
+        .ok_or_else(|| ClogError::CorruptHeader(format!("invalid {}", what)))
+}
+
+/// fills as much of `buf` as the file allows and returns how many bytes were read
+fn read_up_to(file: &mut impl Read, buf: &mut [u8]) -> Result<usize> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match file.read(&mut buf[filled..])? {
+            0 => break,
+            n => filled += n,
+        }
+    }
+    Ok(filled)
+}
+
 #[cfg(test)]
-mod test {}
+mod test {
+    use super::*;
+    use crate::backend::{
+        test_utils::temp_clogfile,
+        user::utils::generate_keys::{generate_base64_nonce, generate_base64_salt},
+    };
+
+    #[test]
+    fn parse_header_rejects_garbage() {
+        let path = temp_clogfile("garbage_header");
+        std::fs::write(&path, "not a clog file\n").unwrap();
+
+        assert!(matches!(
+            parse_header_from_file(&path),
+            Err(ClogError::CorruptHeader(_))
+        ));
+    }
+
+    #[test]
+    fn parse_header_rejects_truncated_file() {
+        let path = temp_clogfile("truncated_header");
+        std::fs::write(&path, "clog @1.0.1\nabc.def").unwrap();
+
+        assert!(matches!(
+            parse_header_from_file(&path),
+            Err(ClogError::CorruptHeader(_))
+        ));
+
+        std::fs::write(&path, &MAGIC[..]).unwrap();
+        assert!(matches!(
+            parse_header_from_file(&path),
+            Err(ClogError::CorruptHeader(_))
+        ));
+    }
+
+    #[test]
+    fn header_round_trips_key_slots_and_large_offsets() {
+        let path = temp_clogfile("v3_header");
+        let slot = |user: &str| KeySlot {
+            user: user.to_string(),
+            kdf: Kdf::Scrypt {
+                log_n: 15,
+                r: 8,
+                p: 1,
+            },
+            base64_salt: generate_base64_salt(),
+            base64_nonce: generate_base64_nonce(),
+            wrapped_key: vec![7; WRAPPED_KEY_LENGTH],
+        };
+        let mut header = Header::new(&generate_base64_nonce(), vec![slot("alice"), slot("bob")]);
+        header.metadata_offset = 5_000_000_000;
+        header.metadata_length = 123_456_789;
+        std::fs::write(&path, header.to_bytes().unwrap()).unwrap();
+
+        let parsed = parse_header_from_file(&path).unwrap();
+        assert_eq!(parsed.version, FORMAT_VERSION);
+        assert_eq!(parsed.base64_nonce, header.base64_nonce);
+        assert_eq!(parsed.metadata_offset, 5_000_000_000);
+        assert_eq!(parsed.metadata_length, 123_456_789);
+        assert_eq!(parsed.key_slots.len(), 2);
+        assert_eq!(parsed.key_slots[1].user, "bob");
+        assert_eq!(parsed.key_slots[1].kdf, header.key_slots[1].kdf);
+        assert_eq!(
+            parsed.key_slots[1].base64_salt,
+            header.key_slots[1].base64_salt
+        );
+        assert_eq!(
+            parsed.key_slots[1].wrapped_key,
+            header.key_slots[1].wrapped_key
+        );
+
+        header.key_slots = (0..=KEY_SLOTS).map(|i| slot(&i.to_string())).collect();
+        assert!(matches!(header.to_bytes(), Err(ClogError::KeySlotsFull)));
+    }
+
+    #[test]
+    fn v2_header_is_still_parsed() {
+        let path = temp_clogfile("v2_header");
+        let salt = generate_base64_salt();
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend_from_slice(&2u16.to_le_bytes());
+        bytes.extend_from_slice(&general_purpose::STANDARD.decode(&salt).unwrap());
+        bytes.extend_from_slice(&[0; 12]);
+        bytes.extend_from_slice(&5_000_000_000u64.to_le_bytes());
+        bytes.extend_from_slice(&312u64.to_le_bytes());
+        std::fs::write(&path, &bytes).unwrap();
+
+        let parsed = parse_header_from_file(&path).unwrap();
+        assert_eq!(parsed.version, 2);
+        assert_eq!(parsed.key_slots[0].base64_salt, salt);
+        assert!(parsed.key_slots[0].is_legacy());
+        assert_eq!(parsed.metadata_offset, 5_000_000_000);
+        assert_eq!(parsed.metadata_length, 312);
+        assert!(parsed.to_bytes().is_err());
+    }
+
+    #[test]
+    fn v3_key_slots_are_read_as_pbkdf2() {
+        let path = temp_clogfile("v3_header");
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend_from_slice(&3u16.to_le_bytes());
+        bytes.extend_from_slice(&[0; 12]);
+        bytes.extend_from_slice(&(V3_HEADER_LENGTH as u64).to_le_bytes());
+        bytes.extend_from_slice(&0u64.to_le_bytes());
+        bytes.push(5);
+        bytes.extend_from_slice(b"alice");
+        bytes.resize(38 + V3_KEY_SLOT_LENGTH - WRAPPED_KEY_LENGTH, 1);
+        bytes.extend_from_slice(&[2; WRAPPED_KEY_LENGTH]);
+        bytes.resize(V3_HEADER_LENGTH, 0);
+        std::fs::write(&path, &bytes).unwrap();
+
+        let parsed = parse_header_from_file(&path).unwrap();
+        assert_eq!(parsed.version, 3);
+        assert_eq!(parsed.metadata_offset, V3_HEADER_LENGTH);
+        assert_eq!(parsed.key_slots.len(), 1);
+        assert_eq!(parsed.key_slots[0].user, "alice");
+        assert_eq!(parsed.key_slots[0].kdf, LEGACY_KDF);
+        assert_eq!(parsed.key_slots[0].wrapped_key, [2; WRAPPED_KEY_LENGTH]);
+        assert!(parsed.to_bytes().is_err());
+    }
+
+    #[test]
+    fn v1_header_is_still_parsed() {
+        let path = temp_clogfile("v1_header");
+        let salt = generate_base64_salt();
+        let nonce = generate_base64_nonce();
+        std::fs::write(
+            &path,
+            format!("clog @1.0.1\n{}.{}.{:08}.{:08}\n", salt, nonce, 312, 72),
+        )
+        .unwrap();
+
+        let parsed = parse_header_from_file(&path).unwrap();
+        assert_eq!(parsed.version, 1);
+        assert_eq!(parsed.metadata_length, 312);
+        assert_eq!(parsed.metadata_offset, 72);
+        assert_eq!(parsed.key_slots[0].base64_salt, salt);
+        assert!(parsed.to_bytes().is_err());
+    }
+
+    #[test]
+    fn newer_versions_are_refused() {
+        let path = temp_clogfile("newer_header");
+        let mut bytes = Header::new(&generate_base64_nonce(), Vec::new())
+            .to_bytes()
+            .unwrap();
+        bytes[8..10].copy_from_slice(&(FORMAT_VERSION + 1).to_le_bytes());
+        std::fs::write(&path, &bytes).unwrap();
+
+        assert!(matches!(
+            parse_header_from_file(&path),
+            Err(ClogError::UnsupportedVersion(_))
+        ));
+
+        std::fs::write(&path, "clog @2.0.0\nabc.def.00000000.00000000\n").unwrap();
+        assert!(matches!(
+            parse_header_from_file(&path),
+            Err(ClogError::UnsupportedVersion(_))
+        ));
+    }
+}
@@ -1,11 +1,16 @@
 use super::{
+    clock::SystemClock,
+    error::{ClogError, Result},
     file_operations::{
-        content::{add_file_with_content, decrypt_content_from_file, edit_file_with_content},
+        lock::{LockPolicy, lock_shared},
         utils::make_new_clogfile,
     },
+    header::utils::parse_header_from_file,
     metadata::metadata::Metadata,
+    recover::RecoveryReport,
+    session::{Clog, FolderListing, Kdf, RolloverReport, VersionInfo},
+    verify::VerifyReport,
 };
-use chrono::Local;
 use serde_json::{Value, json};
 use std::path::PathBuf;
 
@@ -14,27 +19,50 @@ pub fn get_file_content(
     filename: &str,
     foldername: &str,
     password: &str,
-) -> String {
-    let metadata = Metadata::extract_metadata_from_file(clogfile_path, password);
-    decrypt_content_from_file(&metadata, foldername, filename, clogfile_path)
+) -> Result<String> {
+    Clog::open(clogfile_path, password)?.read_file(foldername, filename)
 }
 
-pub fn add_new_user(clogfile_path: &PathBuf, password: &str) {
-    make_new_clogfile(&password.to_string(), clogfile_path);
+pub fn add_new_user(clogfile_path: &PathBuf, password: &str) -> Result<()> {
+    make_new_clogfile(password, clogfile_path, Kdf::default(), &SystemClock)
 }
 
-pub fn get_clean_metadata(password: &str, clogfile_path: &PathBuf) -> String {
+/// Ok(false) only when the password is wrong, a damaged clogfile is still an error
+pub fn verify_password(clogfile_path: &PathBuf, password: &str) -> Result<bool> {
+    let _lock = lock_shared(clogfile_path, LockPolicy::default())?;
+    let result =
+        Metadata::unlock_key_from_file(clogfile_path, password).and_then(|(_, base64_key)| {
+            Metadata::extract_metadata_with_key(clogfile_path, &base64_key)
+        });
+
+    match result {
+        Ok(_) => Ok(true),
+        Err(ClogError::WrongPassword) => Ok(false),
+        Err(err) => Err(err),
+    }
+}
+
+pub fn get_clean_metadata(password: &str, clogfile_path: &PathBuf) -> Result<String> {
     // Extract full metadata
-    let metadata: Metadata = Metadata::extract_metadata_from_file(clogfile_path, password);
+    let clog = Clog::open(clogfile_path, password)?;
+    let metadata = clog.metadata();
 
     // Prepare JSON map for folders
     let mut folders_json = serde_json::Map::new();
 
-    for (folder_name, folder) in metadata.folders {
+    for (folder_name, folder) in metadata.all_folders() {
         let mut folder_json = serde_json::Map::new();
 
-        for (file_name, file) in folder.files {
-            folder_json.insert(file_name, json!({ "created_at": file.created_at }));
+        for (file_name, file) in &folder.files {
+            folder_json.insert(
+                file_name.clone(),
+                json!({
+                    "seq": file.seq,
+                    "created_at": file.created_at,
+                    "modified_at": file.modified_at,
+                    "version": file.version,
+                }),
+            );
         }
 
         folders_json.insert(folder_name, Value::Object(folder_json));
@@ -45,89 +73,362 @@ pub fn get_clean_metadata(password: &str, clogfile_path: &PathBuf) -> String {
     root_json.insert("folders".to_string(), Value::Object(folders_json));
     root_json.insert("created_at".to_string(), json!(metadata.created_at));
 
-    serde_json::to_string(&Value::Object(root_json)).unwrap()
+    serde_json::to_string(&Value::Object(root_json))
+        .map_err(|err| ClogError::CorruptMetadata(err.to_string()))
 }
+
 pub fn add_file(
+    password: &str,
+    clogfile_path: &PathBuf,
+    filename: &str,
+    file_content: &str,
+) -> Result<()> {
+    let mut clog = Clog::open(clogfile_path, password)?;
+    clog.add_file(filename, file_content)?;
+    clog.flush()
+}
+
+pub fn add_file_to(
     password: &str,
     clogfile_path: &PathBuf,
     filename: &str,
     foldername: &str,
     file_content: &str,
-) {
-    let mut metadata = Metadata::extract_metadata_from_file(clogfile_path, password);
+) -> Result<()> {
+    let mut clog = Clog::open(clogfile_path, password)?;
+    clog.add_file_to(foldername, filename, file_content)?;
+    clog.flush()
+}
 
-    // check if filename exists in folder
-    let folder = metadata.folders.get_mut(foldername).unwrap();
+pub fn mkdir(password: &str, clogfile_path: &PathBuf, folder_path: &str) -> Result<()> {
+    let mut clog = Clog::open(clogfile_path, password)?;
+    clog.mkdir(folder_path)?;
+    clog.flush()
+}
 
-    // if file does exists return error message
-    if folder.files.get(filename).is_some() {
-        return;
-    }
+pub fn rmdir(password: &str, clogfile_path: &PathBuf, folder_path: &str) -> Result<()> {
+    let mut clog = Clog::open(clogfile_path, password)?;
+    clog.rmdir(folder_path)?;
+    clog.flush()
+}
 
-    // extract metadata from file
-    let mut metadata = Metadata::extract_metadata_from_file(clogfile_path, password);
+pub fn seal_folder(password: &str, clogfile_path: &PathBuf, folder_path: &str) -> Result<()> {
+    let mut clog = Clog::open(clogfile_path, password)?;
+    clog.seal_folder(folder_path)?;
+    clog.flush()
+}
 
-    // if file doesn't exist than add file with content
-    add_file_with_content(
-        &mut metadata,
-        &password.to_string(),
-        foldername,
-        filename,
-        file_content,
-        clogfile_path,
-    );
+pub fn ls(password: &str, clogfile_path: &PathBuf, folder_path: &str) -> Result<FolderListing> {
+    Clog::open(clogfile_path, password)?.ls(folder_path)
 }
 
-pub fn add_folder(clogfile_path: &PathBuf, password: &str) {
-    // extract metadata from file
-    let mut metadata = Metadata::extract_metadata_from_file(clogfile_path, password);
+pub fn edit_file(
+    password: &str,
+    clogfile_path: &PathBuf,
+    filename: &str,
+    foldername: &str,
+    new_file_content: &str,
+) -> Result<()> {
+    let mut clog = Clog::open(clogfile_path, password)?;
+    clog.edit_file(foldername, filename, new_file_content)?;
+    clog.flush()
+}
 
-    let foldername = Local::now().format("%d/%m/%Y").to_string();
+pub fn append_to_file(
+    password: &str,
+    clogfile_path: &PathBuf,
+    filename: &str,
+    foldername: &str,
+    text: &str,
+) -> Result<()> {
+    let mut clog = Clog::open(clogfile_path, password)?;
+    clog.append_to_file(foldername, filename, text)?;
+    clog.flush()
+}
 
-    // check if folder exists in metadata
-    if metadata.folders.get(&foldername).is_some() {
-        return;
-    }
+pub fn change_password(
+    clogfile_path: &PathBuf,
+    old_password: &str,
+    new_password: &str,
+) -> Result<()> {
+    let mut clog = Clog::open(clogfile_path, old_password)?;
+    clog.change_password(new_password)
+}
+
+pub fn rekey_kdf(clogfile_path: &PathBuf, password: &str, kdf: Kdf) -> Result<()> {
+    let mut clog = Clog::open(clogfile_path, password)?;
+    clog.rekey_kdf(password, kdf)
+}
 
-    // if folder doesn't exists than make new one using current date
-    metadata.add_latest_folder();
+pub fn add_user(
+    clogfile_path: &PathBuf,
+    password: &str,
+    new_user: &str,
+    new_password: &str,
+) -> Result<()> {
+    let mut clog = Clog::open(clogfile_path, password)?;
+    clog.add_user(new_user, new_password)
+}
 
-    metadata.update_metadata_in_file(clogfile_path, &password.to_owned());
+pub fn remove_user(clogfile_path: &PathBuf, password: &str, user: &str) -> Result<()> {
+    let mut clog = Clog::open(clogfile_path, password)?;
+    clog.remove_user(user)
 }
 
-pub fn edit_file(
+/// user names are stored in plain text, so listing them needs no password
+pub fn list_users(clogfile_path: &PathBuf) -> Result<Vec<String>> {
+    let _lock = lock_shared(clogfile_path, LockPolicy::default())?;
+    let header = parse_header_from_file(clogfile_path)?;
+    Ok(header.key_slots.into_iter().map(|slot| slot.user).collect())
+}
+
+pub fn compact(password: &str, clogfile_path: &PathBuf) -> Result<u64> {
+    let mut clog = Clog::open(clogfile_path, password)?;
+    let freed = clog.compact()?;
+    clog.flush()?;
+    Ok(freed)
+}
+
+pub fn dead_bytes(password: &str, clogfile_path: &PathBuf) -> Result<u64> {
+    Clog::open(clogfile_path, password)?.dead_bytes()
+}
+
+pub fn verify(password: &str, clogfile_path: &PathBuf) -> Result<VerifyReport> {
+    super::verify::verify(clogfile_path, password)
+}
+
+pub fn recover(
+    password: &str,
+    clogfile_path: &PathBuf,
+    recovered_path: &PathBuf,
+) -> Result<RecoveryReport> {
+    super::recover::recover(clogfile_path, password, recovered_path)
+}
+
+pub fn override_file_content(
     password: &str,
     clogfile_path: &PathBuf,
     filename: &str,
     foldername: &str,
     new_file_content: &str,
-) {
-    let mut metadata = Metadata::extract_metadata_from_file(clogfile_path, password);
-
-    edit_file_with_content(
-        &mut metadata,
-        &password.to_string(),
-        foldername,
-        filename,
-        new_file_content,
-        clogfile_path,
-    );
-}
-
-pub fn daily_check_and_update_metadata(clogfile_path: &PathBuf, password: &str) {
-    let mut metadata = Metadata::extract_metadata_from_file(clogfile_path, password);
-    let mut current_folder_exists = false;
-    let current_date = Local::now().format("%d/%m/%Y").to_string();
-
-    for (folder_date, folder) in metadata.folders.iter_mut() {
-        if *folder_date != current_date {
-            folder.make_read_only();
-        } else {
-            current_folder_exists = true;
-        }
+    reason: &str,
+) -> Result<()> {
+    let mut clog = Clog::open(clogfile_path, password)?;
+    clog.override_read_only(reason, |clog| {
+        clog.edit_file(foldername, filename, new_file_content)
+    })?;
+    clog.flush()
+}
 
-        if !current_folder_exists {
-            add_folder(clogfile_path, password);
-        }
+pub fn delete_file(
+    password: &str,
+    clogfile_path: &PathBuf,
+    filename: &str,
+    foldername: &str,
+    wipe: bool,
+) -> Result<()> {
+    let mut clog = Clog::open(clogfile_path, password)?;
+    clog.delete_file(foldername, filename, wipe)?;
+    clog.flush()
+}
+
+pub fn override_delete_file(
+    password: &str,
+    clogfile_path: &PathBuf,
+    filename: &str,
+    foldername: &str,
+    wipe: bool,
+    reason: &str,
+) -> Result<()> {
+    let mut clog = Clog::open(clogfile_path, password)?;
+    clog.override_read_only(reason, |clog| clog.delete_file(foldername, filename, wipe))?;
+    clog.flush()
+}
+
+pub fn rename_file(
+    password: &str,
+    clogfile_path: &PathBuf,
+    filename: &str,
+    foldername: &str,
+    new_filename: &str,
+) -> Result<()> {
+    let mut clog = Clog::open(clogfile_path, password)?;
+    clog.rename_file(foldername, filename, new_filename)?;
+    clog.flush()
+}
+
+pub fn move_file(
+    password: &str,
+    clogfile_path: &PathBuf,
+    filename: &str,
+    foldername: &str,
+    new_foldername: &str,
+) -> Result<()> {
+    let mut clog = Clog::open(clogfile_path, password)?;
+    clog.move_file(foldername, new_foldername, filename)?;
+    clog.flush()
+}
+
+pub fn list_versions(
+    password: &str,
+    clogfile_path: &PathBuf,
+    filename: &str,
+    foldername: &str,
+) -> Result<Vec<VersionInfo>> {
+    Clog::open(clogfile_path, password)?.list_versions(foldername, filename)
+}
+
+pub fn get_version(
+    password: &str,
+    clogfile_path: &PathBuf,
+    filename: &str,
+    foldername: &str,
+    version: u64,
+) -> Result<String> {
+    Clog::open(clogfile_path, password)?.get_version(foldername, filename, version)
+}
+
+pub fn restore_version(
+    password: &str,
+    clogfile_path: &PathBuf,
+    filename: &str,
+    foldername: &str,
+    version: u64,
+) -> Result<()> {
+    let mut clog = Clog::open(clogfile_path, password)?;
+    clog.restore_version(foldername, filename, version)?;
+    clog.flush()
+}
+
+pub fn set_version_limit(password: &str, clogfile_path: &PathBuf, limit: usize) -> Result<()> {
+    let mut clog = Clog::open(clogfile_path, password)?;
+    clog.set_version_limit(limit)?;
+    clog.flush()
+}
+
+pub fn daily_check_and_update_metadata(
+    clogfile_path: &PathBuf,
+    password: &str,
+) -> Result<RolloverReport> {
+    let mut clog = Clog::open(clogfile_path, password)?;
+    clog.roll_over()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::backend::test_utils::{TEST_KDF, make_test_clogfile, temp_clogfile};
+    use chrono::Local;
+
+    #[test]
+    fn wrong_password_is_an_error() {
+        let path = temp_clogfile("wrong_password");
+        make_test_clogfile("right", &path);
+
+        assert!(matches!(
+            get_clean_metadata("wrong", &path),
+            Err(ClogError::WrongPassword)
+        ));
+    }
+
+    #[test]
+    fn verify_password_tells_wrong_password_from_corruption() {
+        use crate::backend::{
+            file_operations::content::encrypt_data,
+            header::utils::{HEADER_LENGTH, Header},
+            user::{
+                key_slot::KeySlot,
+                utils::generate_keys::{generate_base64_key, generate_base64_nonce},
+            },
+        };
+
+        let path = temp_clogfile("verify_password");
+        make_test_clogfile("pw", &path);
+        assert!(verify_password(&path, "pw").unwrap());
+        assert!(!verify_password(&path, "wrong").unwrap());
+
+        // a damaged entry is not a wrong password
+        let clog = Clog::open(&path, "pw").unwrap();
+        let folder = clog.list().unwrap()[0].folder.clone();
+        let offset = clog.metadata().get_file(&folder, "Welcome").unwrap().offset;
+        let mut bytes = std::fs::read(&path).unwrap();
+        bytes[offset] ^= 0xFF;
+        std::fs::write(&path, &bytes).unwrap();
+        let err = clog.read_file(&folder, "Welcome").unwrap_err();
+        assert!(matches!(err, ClogError::CorruptEntry { .. }));
+        assert!(err.is_corruption());
+        assert!(verify_password(&path, "pw").unwrap());
+
+        // nor a damaged metadata record, the key slot already took the password
+        let metadata_offset = parse_header_from_file(&path).unwrap().metadata_offset;
+        let mut damaged = bytes.clone();
+        damaged[metadata_offset] ^= 0xFF;
+        std::fs::write(&path, &damaged).unwrap();
+        let err = verify_password(&path, "pw").unwrap_err();
+        assert!(matches!(err, ClogError::CorruptMetadata(_)));
+        assert!(err.is_corruption());
+        assert!(!verify_password(&path, "wrong").unwrap());
+
+        // neither is a malformed header
+        std::fs::write(&path, &bytes[..20]).unwrap();
+        let err = verify_password(&path, "pw").unwrap_err();
+        assert!(matches!(err, ClogError::CorruptHeader(_)));
+        assert!(err.is_corruption());
+
+        // nor metadata that decrypts to something other than JSON
+        let base64_key = generate_base64_key();
+        let key_slot = KeySlot::new("owner", "pw", TEST_KDF, &base64_key).unwrap();
+        let mut header = Header::new(&generate_base64_nonce(), vec![key_slot]);
+        let metadata = encrypt_data(b"{ not json", &base64_key, &header.base64_nonce).unwrap();
+        header.metadata_length = metadata.len();
+        let mut bytes = header.to_bytes().unwrap();
+        bytes.extend_from_slice(&metadata);
+        assert_eq!(header.metadata_offset, HEADER_LENGTH);
+        std::fs::write(&path, &bytes).unwrap();
+        assert!(matches!(
+            verify_password(&path, "pw"),
+            Err(ClogError::InvalidMetadataJson(_))
+        ));
+        assert!(!verify_password(&path, "wrong").unwrap());
+    }
+
+    #[test]
+    fn missing_and_duplicate_files_are_errors() {
+        let path = temp_clogfile("missing_file");
+        make_test_clogfile("pw", &path);
+        let today = Local::now().format("%d/%m/%Y").to_string();
+
+        assert!(matches!(
+            get_file_content(&path, "nope", &today, "pw"),
+            Err(ClogError::NotFound { .. })
+        ));
+        assert!(matches!(
+            get_file_content(&path, "Welcome", "01/01/1970", "pw"),
+            Err(ClogError::FolderNotFound { .. })
+        ));
+        assert!(matches!(
+            add_file("pw", &path, "Welcome", "again"),
+            Err(ClogError::AlreadyExists { .. })
+        ));
+        assert!(add_new_user(&path, "pw").is_err());
+    }
+
+    #[test]
+    fn add_edit_and_read_back() {
+        let path = temp_clogfile("round_trip");
+        make_test_clogfile("pw", &path);
+        let today = Local::now().format("%d/%m/%Y").to_string();
+
+        add_file("pw", &path, "first", "hello").unwrap();
+        edit_file("pw", &path, "Welcome", &today, "edited welcome").unwrap();
+
+        assert_eq!(
+            get_file_content(&path, "first", &today, "pw").unwrap(),
+            "hello"
+        );
+        assert_eq!(
+            get_file_content(&path, "Welcome", &today, "pw").unwrap(),
+            "edited welcome"
+        );
     }
 }
@@ -0,0 +1,51 @@
+use crate::backend::clock::Clock;
+use serde::{Deserialize, Serialize};
+
+/// What was done to an entry of a read-only folder.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum AuditAction {
+    Edit,
+    Delete,
+    Rename,
+    Move,
+    Add,
+    Mkdir,
+    Rmdir,
+    Restore,
+    Append,
+}
+
+/// One change made to a read-only folder through [`Clog::override_read_only`].
+///
+/// [`Clog::override_read_only`]: crate::Clog::override_read_only
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct AuditRecord {
+    /// RFC 3339 with offset, like the times of entries
+    pub at: String,
+    pub user: String,
+    pub action: AuditAction,
+    pub folder: String,
+    pub file: String,
+    pub reason: String,
+}
+
+impl AuditRecord {
+    pub fn new(
+        user: &str,
+        action: AuditAction,
+        folder: &str,
+        file: &str,
+        reason: &str,
+        clock: &dyn Clock,
+    ) -> Self {
+        AuditRecord {
+            at: clock.timestamp(),
+            user: user.to_string(),
+            action,
+            folder: folder.to_string(),
+            file: file.to_string(),
+            reason: reason.to_string(),
+        }
+    }
+}
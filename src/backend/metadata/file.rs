@@ -1,50 +1,119 @@
+use crate::backend::clock::Clock;
 use aes_gcm::aead::{OsRng, rand_core::RngCore};
 use base64::{Engine as _, engine::general_purpose};
-use chrono::Local;
 use serde::{Deserialize, Serialize};
 
 #[derive(Serialize, Deserialize, Clone)]
 pub struct File {
+    /// creation order within the clogfile, unique and never reused
+    #[serde(default)]
+    pub seq: u64,
+    /// RFC 3339 with offset
     pub created_at: String,
+    /// RFC 3339 with offset, the last time the content was replaced
+    #[serde(default)]
+    pub modified_at: String,
     pub offset: usize,
     pub length: usize,
     key: String,
     nonce: String,
+    /// number of the current content, counts up with every edit
+    #[serde(default = "first_version")]
+    pub version: u64,
+    /// text appended since the content was last replaced, in order
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub chunks: Vec<Chunk>,
+    /// earlier contents kept by the version history, oldest first
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub versions: Vec<Version>,
+}
+
+/// An earlier content of a file, encrypted with the file's key under its own nonce.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Version {
+    /// number the content had while it was current
+    pub number: u64,
+    /// RFC 3339 with offset, when this content was written
+    pub modified_at: String,
+    pub offset: usize,
+    pub length: usize,
+    nonce: String,
+    /// text that was appended to this content, in order
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub chunks: Vec<Chunk>,
+}
+
+/// Text appended to a content, encrypted with the file's key under its own nonce.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Chunk {
+    pub offset: usize,
+    pub length: usize,
+    nonce: String,
+}
+
+impl Chunk {
+    pub fn new(offset: usize, length: usize, nonce: String) -> Self {
+        Chunk {
+            offset,
+            length,
+            nonce,
+        }
+    }
+}
+
+/// (base64_nonce,offset,length) of one ciphertext of a content
+pub type Piece<'a> = (&'a String, usize, usize);
+
+/// Which ciphertext of a file is meant, the current content or a kept version of it,
+/// and either that content itself or one of the chunks appended to it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BlobId {
+    /// number of a kept version, `None` for the current content
+    pub version: Option<u64>,
+    /// index of an appended chunk, `None` for the content itself
+    pub chunk: Option<usize>,
 }
 
 impl File {
-    pub fn new(offset: usize, length: usize) -> Self {
+    pub fn new(seq: u64, offset: usize, length: usize, clock: &dyn Clock) -> Self {
         // fetch current time
-        let created_at = Local::now().format("%I:%M:%S %p").to_string();
+        let created_at = clock.timestamp();
 
         // fill key with random bytes
         let mut key = [0u8; 32];
         OsRng.fill_bytes(&mut key);
-        let key = general_purpose::STANDARD.encode(&key);
+        let key = general_purpose::STANDARD.encode(key);
 
         // fill nonce with random bytes
         let mut nonce = [0u8; 12];
         OsRng.fill_bytes(&mut nonce);
-        let nonce = general_purpose::STANDARD.encode(&nonce);
+        let nonce = general_purpose::STANDARD.encode(nonce);
 
         File {
+            seq,
+            modified_at: created_at.clone(),
             created_at,
             offset,
             length,
             key,
             nonce,
+            version: first_version(),
+            chunks: Vec::new(),
+            versions: Vec::new(),
         }
     }
 
     pub fn update_nonce(&mut self) {
         let mut tmp_nonce = [0u8; 12];
         OsRng.fill_bytes(&mut tmp_nonce);
-        let tmp_nonce = general_purpose::STANDARD.encode(&tmp_nonce);
+        let tmp_nonce = general_purpose::STANDARD.encode(tmp_nonce);
         self.nonce = tmp_nonce;
     }
 
-    pub fn update_offset(&mut self, delta_offset: isize) {
-        self.offset = (self.offset as isize + delta_offset) as usize;
+    /// marks the content as replaced
+    pub fn touch(&mut self, clock: &dyn Clock) {
+        self.modified_at = clock.timestamp();
+        self.version += 1;
     }
 
     pub fn update_length(&mut self, length: usize) {
@@ -55,4 +124,138 @@ impl File {
     pub fn get_file_parameters(&self) -> (&String, &String, usize, usize) {
         (&self.key, &self.nonce, self.offset, self.length)
     }
+
+    /// gives the key and the (base64_nonce,offset,length) of every ciphertext making up
+    /// the content numbered `number`, the current one included, in the order they are read
+    pub fn get_version_parameters(&self, number: u64) -> Option<(&String, Vec<Piece<'_>>)> {
+        let (nonce, offset, length, chunks) = match number == self.version {
+            true => (&self.nonce, self.offset, self.length, &self.chunks),
+            false => self
+                .versions
+                .iter()
+                .find(|version| version.number == number)
+                .map(|version| {
+                    (
+                        &version.nonce,
+                        version.offset,
+                        version.length,
+                        &version.chunks,
+                    )
+                })?,
+        };
+
+        let mut pieces = vec![(nonce, offset, length)];
+        pieces.extend(
+            chunks
+                .iter()
+                .map(|chunk| (&chunk.nonce, chunk.offset, chunk.length)),
+        );
+        Some((&self.key, pieces))
+    }
+
+    /// records text appended to the current content
+    pub fn push_chunk(&mut self, chunk: Chunk, clock: &dyn Clock) {
+        self.chunks.push(chunk);
+        self.modified_at = clock.timestamp();
+    }
+
+    /// keeps the current content and its chunks as a version, before an edit moves the
+    /// file elsewhere
+    pub fn keep_version(&mut self) {
+        self.versions.push(Version {
+            number: self.version,
+            modified_at: self.modified_at.clone(),
+            offset: self.offset,
+            length: self.length,
+            nonce: self.nonce.clone(),
+            chunks: std::mem::take(&mut self.chunks),
+        });
+    }
+
+    /// drops the oldest versions until at most `limit` are left
+    pub fn prune_versions(&mut self, limit: usize) {
+        let excess = self.versions.len().saturating_sub(limit);
+        self.versions.drain(..excess);
+    }
+
+    /// every ciphertext of the file, the current content first
+    pub fn blobs(&self) -> Vec<BlobId> {
+        let contents = std::iter::once((None, self.chunks.len())).chain(
+            self.versions
+                .iter()
+                .map(|version| (Some(version.number), version.chunks.len())),
+        );
+
+        let mut ids = Vec::new();
+        for (version, chunks) in contents {
+            ids.push(BlobId {
+                version,
+                chunk: None,
+            });
+            ids.extend((0..chunks).map(|chunk| BlobId {
+                version,
+                chunk: Some(chunk),
+            }));
+        }
+        ids
+    }
+
+    /// (offset,length) of a ciphertext of the file
+    pub fn blob(&self, id: BlobId) -> Option<(usize, usize)> {
+        self.blob_parameters(id)
+            .map(|(_, _, offset, length)| (offset, length))
+    }
+
+    /// gives (base64_key,base64_nonce,offset,length) of a ciphertext of the file
+    pub fn blob_parameters(&self, id: BlobId) -> Option<(&String, &String, usize, usize)> {
+        let (nonce, offset, length, chunks) = match id.version {
+            None => (&self.nonce, self.offset, self.length, &self.chunks),
+            Some(number) => self
+                .versions
+                .iter()
+                .find(|version| version.number == number)
+                .map(|version| {
+                    (
+                        &version.nonce,
+                        version.offset,
+                        version.length,
+                        &version.chunks,
+                    )
+                })?,
+        };
+        match id.chunk {
+            None => Some((&self.key, nonce, offset, length)),
+            Some(index) => chunks
+                .get(index)
+                .map(|chunk| (&self.key, &chunk.nonce, chunk.offset, chunk.length)),
+        }
+    }
+
+    /// (offset,length) of a ciphertext of the file, for moving it elsewhere
+    pub fn blob_mut(&mut self, id: BlobId) -> Option<(&mut usize, &mut usize)> {
+        let (offset, length, chunks) = match id.version {
+            None => (&mut self.offset, &mut self.length, &mut self.chunks),
+            Some(number) => self
+                .versions
+                .iter_mut()
+                .find(|version| version.number == number)
+                .map(|version| {
+                    (
+                        &mut version.offset,
+                        &mut version.length,
+                        &mut version.chunks,
+                    )
+                })?,
+        };
+        match id.chunk {
+            None => Some((offset, length)),
+            Some(index) => chunks
+                .get_mut(index)
+                .map(|chunk| (&mut chunk.offset, &mut chunk.length)),
+        }
+    }
+}
+
+fn first_version() -> u64 {
+    1
 }
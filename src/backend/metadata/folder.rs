@@ -1,24 +1,68 @@
-use super::file::File;
+use super::{file::File, path::ClogPath, rollover::FOLDER_DATE_FORMAT};
+use crate::backend::clock::Clock;
+use chrono::NaiveDate;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 
-#[derive(Serialize, Deserialize)]
+/// What a folder is for, which decides when it can be changed.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum FolderKind {
+    /// a top-level day folder named `dd/mm/yyyy`, only changed on that day and sealed
+    /// by the rollover afterwards
+    #[default]
+    Dated,
+    /// a folder named by the user, changed until it is sealed explicitly
+    Named,
+}
+
+impl FolderKind {
+    /// top-level folders named after a date are day folders, everything else is named
+    pub fn of(path: &ClogPath) -> Self {
+        match path.components() {
+            [name] if NaiveDate::parse_from_str(name, FOLDER_DATE_FORMAT).is_ok() => {
+                FolderKind::Dated
+            }
+            _ => FolderKind::Named,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone)]
 pub struct Folder {
+    #[serde(default)]
+    pub kind: FolderKind,
     pub files: HashMap<String, File>,
+    /// folders nested inside this one
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub folders: HashMap<String, Folder>,
     read_only: bool,
 }
 
 impl Folder {
-    pub fn new() -> Self {
+    pub fn new(kind: FolderKind) -> Self {
         Folder {
+            kind,
             files: HashMap::new(),
+            folders: HashMap::new(),
             read_only: false,
         }
     }
 
-    pub fn add_file(&mut self, filename: &str, offset: usize, length: usize) {
+    pub fn is_empty(&self) -> bool {
+        self.files.is_empty() && self.folders.is_empty()
+    }
+
+    pub fn add_file(
+        &mut self,
+        filename: &str,
+        seq: u64,
+        offset: usize,
+        length: usize,
+        clock: &dyn Clock,
+    ) {
         let key = filename.to_string();
-        let value = File::new(offset, length);
+        let value = File::new(seq, offset, length, clock);
         self.files.insert(key, value);
     }
 
@@ -30,18 +74,11 @@ impl Folder {
         self.read_only
     }
 
-    pub fn update_nonce(&mut self, filename: &str) {
-        let file = self.files.get_mut(filename).unwrap();
-        file.update_nonce();
-    }
-
-    pub fn update_offset(&mut self, filename: &str, delta_offset: isize) {
-        let file = self.files.get_mut(filename).unwrap();
-        file.update_offset(delta_offset);
-    }
-
-    pub fn update_length(&mut self, filename: &str, length: usize) {
-        let file = self.files.get_mut(filename).unwrap();
-        file.update_length(length);
+    /// whether the folder called `name` takes no changes on `today`: sealed folders
+    /// never do, and dated folders stop once their day is over, sealed or not
+    pub fn is_read_only_on(&self, name: &str, today: NaiveDate) -> bool {
+        let day_is_over =
+            || NaiveDate::parse_from_str(name, FOLDER_DATE_FORMAT).is_ok_and(|date| date < today);
+        self.read_only || (self.kind == FolderKind::Dated && day_is_over())
     }
 }
@@ -1,12 +1,15 @@
 use super::metadata::Metadata;
+use crate::backend::clock::Clock;
 
 /// Gives predefined structure to initialize metadata
 /// {
-///     created_at : "09/05/2025 9:25AM",
+///     created_at : "09/05/2025",
 ///     data : [
 ///         "09/05/2025" : [
 ///                 "welcome.txt" : {
-///                     created_at : "9:30 AM",
+///                     seq : 0,
+///                     created_at : "2025-05-09T09:30:00.000000000+02:00",
+///                     modified_at : "2025-05-09T09:30:00.000000000+02:00",
 ///                     offset : 72,
 ///                     length : 0,
 ///                     key : "randomely_generated_32bytes_key",
@@ -17,8 +20,8 @@ use super::metadata::Metadata;
 ///     ]
 /// }
 ///
-pub fn init() -> Metadata {
-    let mut metadata = Metadata::new();
-    metadata.add_latest_folder();
+pub fn init(clock: &dyn Clock) -> Metadata {
+    let mut metadata = Metadata::new(clock);
+    metadata.add_latest_folder(clock);
     metadata
 }
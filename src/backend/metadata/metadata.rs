@@ -1,196 +1,398 @@
-use super::{file::File, folder::Folder};
+use super::{
+    audit::{AuditAction, AuditRecord},
+    file::File,
+    folder::{Folder, FolderKind},
+    path::{ClogPath, check_entry_name},
+    rollover::dated_folder_name,
+};
 use crate::backend::{
+    clock::Clock,
+    error::{ClogError, Result},
     file_operations::{
-        content::parse_base64_encrypted_data,
-        utils::{open_file_read, open_file_read_write},
-    },
-    header::utils::{
-        parse_header_from_file, update_metadata_offset_and_length_in_file, update_nonce_in_file,
+        content::{decrypt_data, encrypt_data, read_exact_or_corrupt},
+        utils::{open_file_read, open_file_read_write, sync_bytes, write_bytes},
     },
-    user::utils::derive_key::derive_key_base64,
+    header::utils::{Header, parse_header_from_file, write_metadata_pointer},
+    user::{key_slot::unlock_master_key, utils::generate_keys::generate_base64_nonce},
 };
-use aes_gcm::{Aes256Gcm, Key, KeyInit, Nonce, aead::Aead};
-use base64::{Engine, engine::general_purpose};
-use chrono::Local;
+use chrono::NaiveDate;
 use serde::{Deserialize, Serialize};
 use std::{
     collections::HashMap,
-    io::{Read, Seek, SeekFrom, Write},
+    io::{Seek, SeekFrom},
     path::PathBuf,
-    usize,
 };
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, Clone)]
 pub struct Metadata {
     pub folders: HashMap<String, Folder>,
     pub created_at: String,
+    /// every change made to a read-only folder, oldest first
+    #[serde(default)]
+    pub audit_log: Vec<AuditRecord>,
+    /// sequence number the next entry gets
+    #[serde(default)]
+    pub next_seq: u64,
+    /// how many earlier contents every entry keeps, 0 turns the version history off
+    #[serde(default)]
+    pub version_limit: usize,
 }
 
 impl Metadata {
-    pub fn new() -> Self {
-        let created_at = Local::now().format("%d/%m/%Y").to_string();
+    pub fn new(clock: &dyn Clock) -> Self {
+        let created_at = dated_folder_name(clock.today());
         Metadata {
             folders: HashMap::new(),
             created_at,
+            audit_log: Vec::new(),
+            next_seq: 0,
+            version_limit: 0,
         }
     }
 
-    pub fn add_latest_folder(&mut self) {
-        let date = Local::now().format("%d/%m/%Y").to_string();
-        let key = self.folders.contains_key(&date);
-
-        match key {
-            true => println!("{} folder already exists", date),
-            false => {
-                let folder = Folder::new();
-                self.folders.insert(date, folder);
-            }
-        }
+    /// adds today's folder, nothing to do if it already exists
+    pub fn add_latest_folder(&mut self, clock: &dyn Clock) {
+        let date = dated_folder_name(clock.today());
+        self.folders
+            .entry(date)
+            .or_insert_with(|| Folder::new(FolderKind::Dated));
     }
 
-    fn get_all_files_under_folder(&self, foldername: &str) -> &HashMap<String, File> {
-        &self.folders.get(foldername).unwrap().files
+    /// `foldername` is a top-level folder name or a folder path like `25/05/2025/ideas`
+    pub fn get_folder(&self, foldername: &str) -> Result<&Folder> {
+        self.folder_chain(foldername)?
+            .pop()
+            .ok_or_else(|| ClogError::InvalidPath("the root is not a folder".to_string()))
     }
 
-    pub fn update_file_length(&mut self, foldername: &str, filename: &str, length: usize) {
-        let filemap = self.folders.get_mut(foldername).unwrap();
-        let file = filemap.files.get_mut(filename).unwrap();
-        file.update_length(length);
+    pub fn get_folder_mut(&mut self, foldername: &str) -> Result<&mut Folder> {
+        let not_found = || ClogError::FolderNotFound {
+            folder: foldername.to_string(),
+        };
+        let components = self.folder_components(foldername)?;
+        let (first, rest) = components
+            .split_first()
+            .ok_or_else(|| ClogError::InvalidPath("the root is not a folder".to_string()))?;
+
+        let mut folder = self.folders.get_mut(first).ok_or_else(not_found)?;
+        for name in rest {
+            folder = folder.folders.get_mut(name).ok_or_else(not_found)?;
+        }
+        Ok(folder)
     }
 
-    fn get_serialized_metadata(&self) -> String {
-        serde_json::to_string(self).unwrap()
+    /// whether `foldername` or any folder it is nested in takes no changes on `today`
+    pub fn is_read_only(&self, foldername: &str, today: NaiveDate) -> Result<bool> {
+        let components = self.folder_components(foldername)?;
+        let chain = self.folder_chain(foldername)?;
+        Ok(components
+            .iter()
+            .zip(chain)
+            .any(|(name, folder)| folder.is_read_only_on(name, today)))
     }
 
-    /// gets nonce and salt from file
-    /// and convert given metadata to base64_encrypted_metadata using user password
-
-    pub fn to_base64_encrypted_metadata(
-        &self,
-        password: &String,
-        clogfile_path: &PathBuf,
-    ) -> String {
-        // get nonce,salt and offset fro metadata
-        let (base64_salt, base64_nonce, _, _, _) = parse_header_from_file(clogfile_path);
-
-        // derive key using password and salt
-        let base64_key = derive_key_base64(password, &base64_salt);
-
-        let serialized_data = self.get_serialized_metadata();
-        let plaintext = serialized_data.as_bytes();
-
-        // decode key and make key for encryption
-        let key_bytes = general_purpose::STANDARD.decode(base64_key).unwrap();
-        let key = Key::<Aes256Gcm>::from_slice(&key_bytes);
+    /// every folder from the top-level one down to `foldername`
+    fn folder_chain(&self, foldername: &str) -> Result<Vec<&Folder>> {
+        let not_found = || ClogError::FolderNotFound {
+            folder: foldername.to_string(),
+        };
+        let components = self.folder_components(foldername)?;
+
+        let mut chain: Vec<&Folder> = Vec::with_capacity(components.len());
+        for name in &components {
+            let folders = match chain.last() {
+                Some(parent) => &parent.folders,
+                None => &self.folders,
+            };
+            chain.push(folders.get(name).ok_or_else(not_found)?);
+        }
+        Ok(chain)
+    }
 
-        // deocde nonce and make nonce for encryption
-        let nonce_bytes = general_purpose::STANDARD.decode(base64_nonce).unwrap();
-        let nonce = Nonce::from_slice(&nonce_bytes);
+    /// top-level folders are found by their name first, whatever it contains
+    fn folder_components(&self, foldername: &str) -> Result<Vec<String>> {
+        if self.folders.contains_key(foldername) {
+            return Ok(vec![foldername.to_string()]);
+        }
+        Ok(ClogPath::parse(foldername)?.components().to_vec())
+    }
 
-        // make cipher
-        let cipher = Aes256Gcm::new(key);
+    /// the folders directly inside `path`, the top-level folders for the root
+    fn subfolders_mut(&mut self, path: &ClogPath) -> Result<&mut HashMap<String, Folder>> {
+        match path.is_root() {
+            true => Ok(&mut self.folders),
+            false => Ok(&mut self.get_folder_mut(&path.to_string())?.folders),
+        }
+    }
 
-        // encrypt plaintext
-        let ciphertext = cipher.encrypt(nonce, plaintext).unwrap();
+    /// creates the empty folder `path`, the folder holding it must exist
+    pub fn make_folder(&mut self, path: &ClogPath) -> Result<()> {
+        let (parent, name) = path
+            .split_last()
+            .ok_or_else(|| ClogError::InvalidPath("the root already exists".to_string()))?;
+
+        let folders = self.subfolders_mut(&parent)?;
+        if folders.contains_key(name) {
+            return Err(ClogError::FolderExists {
+                folder: path.to_string(),
+            });
+        }
+        folders.insert(name.to_string(), Folder::new(FolderKind::of(path)));
+        Ok(())
+    }
 
-        // convert ciphertext
-        let base64_ciphertext = general_purpose::STANDARD.encode(&ciphertext);
+    /// removes the folder `path` if it holds neither entries nor folders
+    pub fn remove_folder(&mut self, path: &ClogPath) -> Result<()> {
+        if !self.get_folder(&path.to_string())?.is_empty() {
+            return Err(ClogError::FolderNotEmpty {
+                folder: path.to_string(),
+            });
+        }
 
-        base64_ciphertext
+        let (parent, name) = path
+            .split_last()
+            .ok_or_else(|| ClogError::InvalidPath("the root can't be removed".to_string()))?;
+        self.subfolders_mut(&parent)?.remove(name);
+        Ok(())
     }
 
-    pub fn add_file(&mut self, filename: &str, foldername: &str, offset: usize, length: usize) {
-        let folder = self.folders.get_mut(foldername);
-        match folder {
-            Some(f) => {
-                f.add_file(filename, offset, length);
+    /// every folder with its path, parents before the folders inside them
+    pub fn all_folders(&self) -> Vec<(String, &Folder)> {
+        let mut stack: Vec<(String, &Folder)> = self
+            .folders
+            .iter()
+            .map(|(name, folder)| (name.clone(), folder))
+            .collect();
+
+        let mut all = Vec::new();
+        while let Some((path, folder)) = stack.pop() {
+            for (name, subfolder) in &folder.folders {
+                stack.push((format!("{}/{}", path, name), subfolder));
             }
-            None => println!(
-                "metadata/metadata.rs/add_file : {} folder doesn't exist",
-                foldername
-            ),
+            all.push((path, folder));
         }
+        all
     }
 
-    pub fn update_file_nonce(&mut self, filename: &String, foldername: &String) {
-        let folder = self.folders.get_mut(foldername);
-        match folder {
-            Some(f) => {
-                f.update_nonce(filename);
-            }
-            None => println!(
-                "metadata/metadata.rs/update_nonce : {} folder doesn't exist",
-                foldername
-            ),
+    /// Fails with [`ClogError::ReadOnlyFolder`] if `foldername` is read-only, unless an
+    /// override `reason` is given, in which case the change is added to the audit log.
+    pub fn authorize_change(
+        &mut self,
+        user: &str,
+        action: AuditAction,
+        foldername: &str,
+        filename: &str,
+        reason: Option<&str>,
+        clock: &dyn Clock,
+    ) -> Result<()> {
+        if !self.is_read_only(foldername, clock.today())? {
+            return Ok(());
         }
+
+        let reason = reason.ok_or_else(|| ClogError::ReadOnlyFolder {
+            folder: foldername.to_string(),
+        })?;
+        self.audit_log.push(AuditRecord::new(
+            user, action, foldername, filename, reason, clock,
+        ));
+        Ok(())
     }
 
-    fn parse_base64_encrypted_metadata(
-        base64_encrypted_metadata: &String,
-        base64_key: &String,
-        base64_nonce: &String,
-    ) -> Self {
-        let metadata_serialized =
-            parse_base64_encrypted_data(base64_encrypted_metadata, base64_key, base64_nonce);
+    pub fn get_file(&self, foldername: &str, filename: &str) -> Result<&File> {
+        self.get_folder(foldername)?
+            .files
+            .get(filename)
+            .ok_or_else(|| ClogError::NotFound {
+                folder: foldername.to_string(),
+                file: filename.to_string(),
+            })
+    }
 
-        // Deserialize metadata to struct
-        serde_json::from_str(&metadata_serialized).unwrap()
+    pub fn get_file_mut(&mut self, foldername: &str, filename: &str) -> Result<&mut File> {
+        self.get_folder_mut(foldername)?
+            .files
+            .get_mut(filename)
+            .ok_or_else(|| ClogError::NotFound {
+                folder: foldername.to_string(),
+                file: filename.to_string(),
+            })
     }
-    pub fn extract_metadata_from_file(clogfile_path: &PathBuf, password: &str) -> Self {
-        // get nonce,salt and offset fro metadata
-        let (base64_salt, base64_nonce, metadata_length, metadata_offset, _) =
-            parse_header_from_file(clogfile_path);
 
-        // derive key using password and salt
-        let base64_key = derive_key_base64(password, &base64_salt);
+    fn get_serialized_metadata(&self) -> Result<String> {
+        serde_json::to_string(self).map_err(|err| ClogError::CorruptMetadata(err.to_string()))
+    }
 
-        let mut file = open_file_read(clogfile_path);
+    /// gets nonce from header
+    /// and convert given metadata to encrypted_metadata using key derived from user password
+    pub fn to_encrypted_metadata(&self, base64_key: &str, header: &Header) -> Result<Vec<u8>> {
+        let serialized_data = self.get_serialized_metadata()?;
+        encrypt_data(serialized_data.as_bytes(), base64_key, &header.base64_nonce)
+    }
 
-        // get the cursur to offset to read metadata
-        file.seek(SeekFrom::Start(metadata_offset.try_into().unwrap()))
-            .unwrap();
+    pub fn add_file(
+        &mut self,
+        filename: &str,
+        foldername: &str,
+        offset: usize,
+        length: usize,
+        clock: &dyn Clock,
+    ) -> Result<()> {
+        let seq = self.next_seq;
+        let folder = self.get_folder_mut(foldername)?;
+        folder.add_file(filename, seq, offset, length, clock);
+        self.next_seq += 1;
+        Ok(())
+    }
 
-        // make a container to store given bytes from file
-        let mut base64_encrypted_metadata_bytes = vec![0u8; metadata_length];
+    /// gives a file a new name within its folder, its content stays where it is
+    pub fn rename_file(
+        &mut self,
+        foldername: &str,
+        old_filename: &str,
+        new_filename: &str,
+    ) -> Result<()> {
+        check_entry_name(new_filename)?;
+        self.move_entry(foldername, old_filename, foldername, new_filename)
+    }
 
-        // read given bytes from file
-        file.read(&mut base64_encrypted_metadata_bytes).unwrap();
+    /// moves a file to another existing folder under the same name
+    pub fn move_file(
+        &mut self,
+        src_foldername: &str,
+        dst_foldername: &str,
+        filename: &str,
+    ) -> Result<()> {
+        self.move_entry(src_foldername, filename, dst_foldername, filename)
+    }
+
+    fn move_entry(
+        &mut self,
+        src_foldername: &str,
+        src_filename: &str,
+        dst_foldername: &str,
+        dst_filename: &str,
+    ) -> Result<()> {
+        self.get_file(src_foldername, src_filename)?;
+        if (src_foldername, src_filename) == (dst_foldername, dst_filename) {
+            return Ok(());
+        }
+        if self
+            .get_folder(dst_foldername)?
+            .files
+            .contains_key(dst_filename)
+        {
+            return Err(ClogError::AlreadyExists {
+                folder: dst_foldername.to_string(),
+                file: dst_filename.to_string(),
+            });
+        }
 
-        // convert given bytes to string
-        let base64_encrypted_metadata = String::from_utf8(base64_encrypted_metadata_bytes).unwrap();
+        let file = self
+            .get_folder_mut(src_foldername)?
+            .files
+            .remove(src_filename)
+            .ok_or_else(|| ClogError::NotFound {
+                folder: src_foldername.to_string(),
+                file: src_filename.to_string(),
+            })?;
+        self.get_folder_mut(dst_foldername)?
+            .files
+            .insert(dst_filename.to_string(), file);
+        Ok(())
+    }
 
-        // get metadata struct from given base64_encrypted_metadata
-        let metadata = Metadata::parse_base64_encrypted_metadata(
-            &base64_encrypted_metadata,
-            &base64_key,
-            &base64_nonce,
-        );
+    pub fn parse_encrypted_metadata(
+        ciphertext: &[u8],
+        base64_key: &str,
+        base64_nonce: &str,
+    ) -> Result<Self> {
+        let metadata_serialized = decrypt_data(ciphertext, base64_key, base64_nonce)?;
 
-        metadata
+        // Deserialize metadata to struct
+        serde_json::from_str(&metadata_serialized)
+            .map_err(|err| ClogError::InvalidMetadataJson(err.to_string()))
     }
 
-    pub fn update_metadata_in_file(&self, clogfile_path: &PathBuf, password: &String) {
-        // first update nonce in file
-        update_nonce_in_file(clogfile_path);
+    /// unlocks the metadata key with the key slot `password` belongs to,
+    /// giving the user of that slot along with the key
+    pub fn unlock_key_from_file(
+        clogfile_path: &PathBuf,
+        password: &str,
+    ) -> Result<(String, String)> {
+        let header = parse_header_from_file(clogfile_path)?;
+        unlock_master_key(&header.key_slots, password)
+    }
 
-        // get current base64_encrypted_metadata
-        let base64_encrypted_metadata = self.to_base64_encrypted_metadata(password, clogfile_path);
+    pub fn extract_metadata_with_key(clogfile_path: &PathBuf, base64_key: &str) -> Result<Self> {
+        // get nonce,length and offset for metadata
+        let header = parse_header_from_file(clogfile_path)?;
 
-        // get offset from header
-        let (_, _, _, metadata_offset, _) = parse_header_from_file(clogfile_path);
+        let mut file = open_file_read(clogfile_path)?;
 
-        let new_metadata_length = base64_encrypted_metadata.len();
+        // get the cursur to offset to read metadata
+        file.seek(SeekFrom::Start(header.metadata_offset as u64))?;
 
-        let mut file = open_file_read_write(clogfile_path);
+        // make a container to store given bytes from file
+        let mut encrypted_metadata = vec![0u8; header.metadata_length];
 
-        // write from that offset and update length in header
-        file.seek(SeekFrom::Start(metadata_offset as u64)).unwrap();
-        file.write_all(base64_encrypted_metadata.as_bytes())
-            .unwrap();
+        // read given bytes from file
+        read_exact_or_corrupt(&mut file, &mut encrypted_metadata, "metadata")?;
+
+        // get metadata struct from given encrypted_metadata
+        let ciphertext = header.decode_blob(encrypted_metadata)?;
+        Metadata::parse_encrypted_metadata(&ciphertext, base64_key, &header.base64_nonce).map_err(
+            |err| match err {
+                // only legacy slots leave checking the password to the metadata,
+                // any other slot that opened already proved it
+                ClogError::WrongPassword
+                    if !header.key_slots.iter().any(|slot| slot.is_legacy()) =>
+                {
+                    ClogError::CorruptMetadata("metadata fails authentication".to_string())
+                }
+                err => err,
+            },
+        )
+    }
 
-        // update length of metadata in header section
-        update_metadata_offset_and_length_in_file(clogfile_path, 0, new_metadata_length);
+    /// 1. Encrypts the metadata under a fresh nonce
+    /// 2. Appends it as a new record at the end of the clogfile, after a snapshot of the
+    ///    header pointing at it, and syncs both
+    /// 3. Points the header at the new record
+    ///
+    /// This is the commit of every change, until the header points at the new record
+    /// readers see the previous one. The previous record is left as dead bytes.
+    pub fn update_metadata_in_file(&self, clogfile_path: &PathBuf, base64_key: &str) -> Result<()> {
+        // 1. Encrypt the metadata under a fresh nonce
+        let mut header = parse_header_from_file(clogfile_path)?;
+        header.base64_nonce = generate_base64_nonce();
+        let encrypted_metadata = self.to_encrypted_metadata(base64_key, &header)?;
+
+        // 2. Append it as a new record and sync it before anything points at it
+        let mut file = open_file_read_write(clogfile_path)?;
+        let end = file.seek(SeekFrom::End(0))? as usize;
+        header.metadata_offset = end + header.snapshot_length();
+        header.metadata_length = encrypted_metadata.len();
+        let mut record = header.to_bytes()?;
+        record.extend_from_slice(&encrypted_metadata);
+        write_bytes(&mut file, &record, "append metadata record")?;
+        sync_bytes(&file, "sync metadata record")?;
+
+        // 3. Point the header at the new record
+        write_metadata_pointer(clogfile_path, &header)
     }
 
-    // pub fn update_file_length_and_offset()
+    /// how many bytes of the clogfile the ciphertexts of every entry, kept version and
+    /// appended chunk take up
+    pub fn live_blob_bytes(&self) -> usize {
+        self.all_folders()
+            .into_iter()
+            .flat_map(|(_, folder)| folder.files.values())
+            .flat_map(|file| {
+                file.blobs()
+                    .into_iter()
+                    .filter_map(|id| file.blob(id))
+                    .map(|(_, length)| length)
+            })
+            .sum()
+    }
 }
@@ -1,4 +1,8 @@
+pub mod audit;
 pub mod file;
 pub mod folder;
 pub mod init;
+#[allow(clippy::module_inception)]
 pub mod metadata;
+pub mod path;
+pub mod rollover;
@@ -0,0 +1,155 @@
+use super::rollover::FOLDER_DATE_FORMAT;
+use crate::backend::error::{ClogError, Result};
+use chrono::NaiveDate;
+use std::{fmt, str::FromStr};
+
+/// A slash separated folder path such as `projects/clog/ideas`.
+///
+/// Day folders are named `dd/mm/yyyy`, so a path starting with such a date keeps it as
+/// its first component: `25/05/2025/ideas` is the folder `ideas` inside the day
+/// folder `25/05/2025`. The empty path, or `/`, is the root holding the top-level folders.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Default)]
+pub struct ClogPath {
+    components: Vec<String>,
+}
+
+impl ClogPath {
+    /// the root, parent of every top-level folder
+    pub fn root() -> Self {
+        ClogPath::default()
+    }
+
+    pub fn parse(path: &str) -> Result<Self> {
+        let trimmed = path.trim_matches('/');
+        if trimmed.is_empty() {
+            return Ok(ClogPath::root());
+        }
+
+        let mut pieces: Vec<&str> = trimmed.split('/').collect();
+        let mut components = Vec::with_capacity(pieces.len());
+
+        // a leading day folder spans three pieces
+        if let [day, month, year, ..] = pieces[..] {
+            let date = format!("{}/{}/{}", day, month, year);
+            if (day.len(), month.len(), year.len()) == (2, 2, 4)
+                && NaiveDate::parse_from_str(&date, FOLDER_DATE_FORMAT).is_ok()
+            {
+                components.push(date);
+                pieces.drain(..3);
+            }
+        }
+
+        for piece in pieces {
+            check_name(piece)
+                .map_err(|reason| ClogError::InvalidPath(format!("'{}' {}", path, reason)))?;
+            components.push(piece.to_string());
+        }
+        Ok(ClogPath { components })
+    }
+
+    pub fn is_root(&self) -> bool {
+        self.components.is_empty()
+    }
+
+    pub fn components(&self) -> &[String] {
+        &self.components
+    }
+
+    /// the folder holding this one and this folder's name, `None` for the root
+    pub fn split_last(&self) -> Option<(ClogPath, &str)> {
+        let (name, parent) = self.components.split_last()?;
+        Some((
+            ClogPath {
+                components: parent.to_vec(),
+            },
+            name,
+        ))
+    }
+
+    /// the path of the folder `name` inside this one
+    pub fn join(&self, name: &str) -> ClogPath {
+        let mut components = self.components.clone();
+        components.push(name.to_string());
+        ClogPath { components }
+    }
+}
+
+/// Fails with [`ClogError::InvalidPath`] unless `name` can stand on its own as the
+/// name of an entry, by the same rules as the components of a path and without a `/`.
+pub fn check_entry_name(name: &str) -> Result<()> {
+    let reason = if name.contains('/') {
+        Err("contains a '/'")
+    } else {
+        check_name(name)
+    };
+    reason.map_err(|reason| ClogError::InvalidPath(format!("name '{}' {}", name, reason)))
+}
+
+/// why `name` can't be a path component
+fn check_name(name: &str) -> std::result::Result<(), &'static str> {
+    match name {
+        "" => Err("has an empty component"),
+        "." | ".." => Err("has a relative component"),
+        _ => Ok(()),
+    }
+}
+
+impl FromStr for ClogPath {
+    type Err = ClogError;
+
+    fn from_str(path: &str) -> Result<Self> {
+        ClogPath::parse(path)
+    }
+}
+
+impl fmt::Display for ClogPath {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.components.join("/"))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn leading_dates_stay_one_component() {
+        let path = ClogPath::parse("25/05/2025/projects/clog/").unwrap();
+        assert_eq!(path.components(), ["25/05/2025", "projects", "clog"]);
+        assert_eq!(path.to_string(), "25/05/2025/projects/clog");
+
+        let (parent, name) = path.split_last().unwrap();
+        assert_eq!(
+            (parent.to_string().as_str(), name),
+            ("25/05/2025/projects", "clog")
+        );
+        assert_eq!(
+            parent.join("ideas").to_string(),
+            "25/05/2025/projects/ideas"
+        );
+
+        assert_eq!(
+            ClogPath::parse("projects/25/05/2025").unwrap().components(),
+            ["projects", "25", "05", "2025"]
+        );
+        assert_eq!(ClogPath::parse("5/5/2025").unwrap().components().len(), 3);
+        assert!(ClogPath::parse("/").unwrap().is_root());
+    }
+
+    #[test]
+    fn bad_components_are_refused() {
+        for path in ["a//b", "a/../b", "./a"] {
+            assert!(matches!(
+                ClogPath::parse(path),
+                Err(ClogError::InvalidPath(_))
+            ));
+        }
+        for name in ["", ".", "..", "a/b", "/"] {
+            assert!(matches!(
+                check_entry_name(name),
+                Err(ClogError::InvalidPath(_))
+            ));
+        }
+        check_entry_name("notes .. later").unwrap();
+    }
+}
@@ -0,0 +1,112 @@
+use super::{
+    folder::{Folder, FolderKind},
+    metadata::Metadata,
+};
+use chrono::NaiveDate;
+
+/// format of the dated folder names
+pub const FOLDER_DATE_FORMAT: &str = "%d/%m/%Y";
+
+/// name of the folder holding the entries of `date`
+pub fn dated_folder_name(date: NaiveDate) -> String {
+    date.format(FOLDER_DATE_FORMAT).to_string()
+}
+
+/// What a day rollover changed.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RolloverReport {
+    /// past folders that were sealed read-only, sorted by date
+    pub sealed: Vec<String>,
+    /// today's folder, if it had to be created
+    pub created: Option<String>,
+}
+
+impl RolloverReport {
+    /// true when the metadata was already up to date
+    pub fn is_empty(&self) -> bool {
+        self.sealed.is_empty() && self.created.is_none()
+    }
+}
+
+impl Metadata {
+    /// Seals every folder dated before `today` that is still writable and creates
+    /// today's folder if it is missing.
+    ///
+    /// Named folders, and dated folders from a later date than `today`, are left alone.
+    pub fn roll_over(&mut self, today: NaiveDate) -> RolloverReport {
+        let mut report = RolloverReport::default();
+
+        let mut past: Vec<(NaiveDate, &String, &mut Folder)> = self
+            .folders
+            .iter_mut()
+            .filter(|(_, folder)| folder.kind == FolderKind::Dated && !folder.is_read_only())
+            .filter_map(|(foldername, folder)| {
+                let date = NaiveDate::parse_from_str(foldername, FOLDER_DATE_FORMAT).ok()?;
+                (date < today).then_some((date, foldername, folder))
+            })
+            .collect();
+        past.sort_by_key(|(date, _, _)| *date);
+
+        for (_, foldername, folder) in past {
+            folder.make_read_only();
+            report.sealed.push(foldername.clone());
+        }
+
+        let today = dated_folder_name(today);
+        if !self.folders.contains_key(&today) {
+            self.folders
+                .insert(today.clone(), Folder::new(FolderKind::Dated));
+            report.created = Some(today);
+        }
+
+        report
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::backend::{clock::SystemClock, metadata::path::ClogPath};
+
+    fn date(day: u32, month: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(2025, month, day).unwrap()
+    }
+
+    #[test]
+    fn rollover_seals_past_folders_once_and_creates_today_once() {
+        let mut metadata = Metadata::new(&SystemClock);
+        for foldername in ["30/12/2024", "24/05/2025", "25/05/2025", "notes"] {
+            let kind = FolderKind::of(&ClogPath::parse(foldername).unwrap());
+            metadata
+                .folders
+                .insert(foldername.to_string(), Folder::new(kind));
+        }
+
+        let report = metadata.roll_over(date(25, 5));
+        assert_eq!(report.sealed, ["30/12/2024", "24/05/2025"]);
+        assert_eq!(report.created, None);
+        assert!(!metadata.get_folder("25/05/2025").unwrap().is_read_only());
+        assert!(!metadata.get_folder("notes").unwrap().is_read_only());
+
+        // running it again on the same day changes nothing
+        assert!(metadata.roll_over(date(25, 5)).is_empty());
+
+        // after midnight yesterday is sealed and today created
+        let report = metadata.roll_over(date(26, 5));
+        assert_eq!(report.sealed, ["25/05/2025"]);
+        assert_eq!(report.created.as_deref(), Some("26/05/2025"));
+        assert!(metadata.roll_over(date(26, 5)).is_empty());
+    }
+
+    #[test]
+    fn folders_from_a_later_date_stay_writable() {
+        let mut metadata = Metadata::new(&SystemClock);
+        metadata
+            .folders
+            .insert("27/05/2025".to_string(), Folder::new(FolderKind::Dated));
+
+        let report = metadata.roll_over(date(26, 5));
+        assert!(report.sealed.is_empty());
+        assert!(!metadata.get_folder("27/05/2025").unwrap().is_read_only());
+    }
+}
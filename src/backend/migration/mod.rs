@@ -0,0 +1,384 @@
+use super::{
+    config::FORMAT_VERSION,
+    error::{ClogError, Result},
+    file_operations::{
+        rewrite::{build_clogfile, read_entry_ciphertexts},
+        utils::{open_file_read, sibling_path, write_file_atomically},
+    },
+    header::utils::{Header, parse_header_from_file},
+    metadata::{
+        file::File,
+        folder::{Folder, FolderKind},
+        metadata::Metadata,
+        path::ClogPath,
+        rollover::FOLDER_DATE_FORMAT,
+    },
+    user::{key_slot::KeySlot, utils::generate_keys::generate_base64_key},
+};
+use chrono::{DateTime, Local, NaiveDate, NaiveTime, SecondsFormat, TimeZone};
+use std::{fs, path::PathBuf};
+
+/// how entries were timestamped before v5
+const LEGACY_TIME_FORMAT: &str = "%I:%M:%S %p";
+
+/// One step that upgrades an in-memory clogfile from version `from` to `from + 1`.
+///
+/// `apply` may replace the key the metadata is encrypted with.
+struct Migration {
+    from: u16,
+    apply: fn(&mut Header, &mut Metadata, &mut String) -> Result<()>,
+}
+
+/// Every known upgrade step, in order.
+const MIGRATIONS: &[Migration] = &[
+    Migration {
+        from: 1,
+        apply: v1_to_v2,
+    },
+    Migration {
+        from: 2,
+        apply: v2_to_v3,
+    },
+    Migration {
+        from: 3,
+        apply: v3_to_v4,
+    },
+    Migration {
+        from: 4,
+        apply: v4_to_v5,
+    },
+    Migration {
+        from: 5,
+        apply: v5_to_v6,
+    },
+    Migration {
+        from: 6,
+        apply: v6_to_v7,
+    },
+    Migration {
+        from: 7,
+        apply: v7_to_v8,
+    },
+    Migration {
+        from: 8,
+        apply: v8_to_v9,
+    },
+];
+
+/// v1 stored base64 text; the ciphertext is decoded while the old file is read,
+/// so nothing beyond the header layout changes.
+fn v1_to_v2(
+    _header: &mut Header,
+    _metadata: &mut Metadata,
+    _base64_key: &mut String,
+) -> Result<()> {
+    Ok(())
+}
+
+/// v3 encrypts the metadata with a random master key kept in key slots, the
+/// existing password becomes the first slot, wrapping the master key under the old salt.
+fn v2_to_v3(header: &mut Header, _metadata: &mut Metadata, base64_key: &mut String) -> Result<()> {
+    let legacy = header
+        .key_slots
+        .first()
+        .filter(|slot| slot.is_legacy())
+        .ok_or_else(|| ClogError::CorruptHeader("v2 clogfile without salt".to_string()))?;
+
+    let master_key = generate_base64_key();
+    let slot = KeySlot::with_password_key(
+        &legacy.user,
+        legacy.kdf,
+        &legacy.base64_salt,
+        base64_key,
+        &master_key,
+    )?;
+    header.key_slots = vec![slot];
+    *base64_key = master_key;
+    Ok(())
+}
+
+/// v4 records the kdf of every key slot, v3 slots are read as the PBKDF2 they always used.
+fn v3_to_v4(
+    _header: &mut Header,
+    _metadata: &mut Metadata,
+    _base64_key: &mut String,
+) -> Result<()> {
+    Ok(())
+}
+
+/// v5 stamps entries with RFC 3339 times and a sequence number. Old entries only kept
+/// a local `%I:%M:%S %p` time, it is completed with the date of their folder in the local
+/// timezone, and entries are numbered in the order they were appended to the file.
+fn v4_to_v5(_header: &mut Header, metadata: &mut Metadata, _base64_key: &mut String) -> Result<()> {
+    let fallback_date = NaiveDate::parse_from_str(&metadata.created_at, FOLDER_DATE_FORMAT).ok();
+
+    let mut files: Vec<&mut File> = Vec::new();
+    for (foldername, folder) in metadata.folders.iter_mut() {
+        let date = NaiveDate::parse_from_str(foldername, FOLDER_DATE_FORMAT)
+            .ok()
+            .or(fallback_date)
+            .unwrap_or_default();
+
+        for file in folder.files.values_mut() {
+            if DateTime::parse_from_rfc3339(&file.created_at).is_err() {
+                let time = NaiveTime::parse_from_str(&file.created_at, LEGACY_TIME_FORMAT)
+                    .unwrap_or_default();
+                let local = date.and_time(time);
+                // a time skipped by a DST change is read as UTC rather than dropped
+                let created_at = Local
+                    .from_local_datetime(&local)
+                    .earliest()
+                    .map(|time| time.fixed_offset())
+                    .unwrap_or_else(|| local.and_utc().fixed_offset());
+                file.created_at = created_at.to_rfc3339_opts(SecondsFormat::Secs, false);
+            }
+            if file.modified_at.is_empty() {
+                file.modified_at = file.created_at.clone();
+            }
+            files.push(file);
+        }
+    }
+
+    // entries are only ever appended, so their position is their creation order
+    files.sort_by_key(|file| file.offset);
+    let count = files.len() as u64;
+    for (seq, file) in files.into_iter().enumerate() {
+        file.seq = seq as u64;
+    }
+    metadata.next_seq = count;
+    Ok(())
+}
+
+/// v6 lets folders hold folders, nothing to convert, but older versions must not open
+/// such files as they would drop the nested folders on their next write.
+fn v5_to_v6(
+    _header: &mut Header,
+    _metadata: &mut Metadata,
+    _base64_key: &mut String,
+) -> Result<()> {
+    Ok(())
+}
+
+/// v7 tells day folders from named ones. Top-level folders named after a date are day
+/// folders as they always were, every other folder was made with `mkdir` and is named.
+fn v6_to_v7(_header: &mut Header, metadata: &mut Metadata, _base64_key: &mut String) -> Result<()> {
+    let mut nested: Vec<&mut Folder> = Vec::new();
+    for (foldername, folder) in metadata.folders.iter_mut() {
+        folder.kind = FolderKind::of(&ClogPath::root().join(foldername));
+        nested.extend(folder.folders.values_mut());
+    }
+
+    while let Some(folder) = nested.pop() {
+        folder.kind = FolderKind::Named;
+        nested.extend(folder.folders.values_mut());
+    }
+    Ok(())
+}
+
+/// v8 can keep earlier contents of an entry, nothing to convert as the history starts
+/// off, but older versions must not open such files as they would lose track of them.
+fn v7_to_v8(
+    _header: &mut Header,
+    _metadata: &mut Metadata,
+    _base64_key: &mut String,
+) -> Result<()> {
+    Ok(())
+}
+
+/// v9 writes a snapshot of the header right before every metadata record, so a damaged
+/// header can be recovered from. The rewrite lays the file out that way, the metadata
+/// itself doesn't change.
+fn v8_to_v9(
+    _header: &mut Header,
+    _metadata: &mut Metadata,
+    _base64_key: &mut String,
+) -> Result<()> {
+    Ok(())
+}
+
+/// Upgrades the clogfile at `clogfile_path` to [`FORMAT_VERSION`] if it was written
+/// in an older layout, using the key the metadata is currently encrypted with.
+///
+/// The original is kept next to it as `<name>.v<old version>.bak`, and the upgraded
+/// file replaces it atomically. Returns whether a migration happened.
+pub fn migrate_if_needed(clogfile_path: &PathBuf, base64_key: &str) -> Result<bool> {
+    let mut header = parse_header_from_file(clogfile_path)?;
+    if header.version == FORMAT_VERSION {
+        return Ok(false);
+    }
+    let mut base64_key = base64_key.to_string();
+
+    // read everything with the old layout before touching the file
+    let mut metadata = Metadata::extract_metadata_with_key(clogfile_path, &base64_key)?;
+    let entries = read_entry_ciphertexts(clogfile_path, &header, &metadata)?;
+
+    let old_version = header.version;
+    upgrade(&mut header, &mut metadata, &mut base64_key)?;
+    let bytes = build_clogfile(&header, &mut metadata, &entries, &base64_key)?;
+
+    backup(clogfile_path, old_version)?;
+    write_file_atomically(clogfile_path, &bytes)?;
+    Ok(true)
+}
+
+/// Runs every migration from the version of `header` up to [`FORMAT_VERSION`] on a
+/// clogfile read into memory, `base64_key` may be replaced along the way.
+pub fn upgrade(
+    header: &mut Header,
+    metadata: &mut Metadata,
+    base64_key: &mut String,
+) -> Result<()> {
+    while header.version < FORMAT_VERSION {
+        let migration = MIGRATIONS
+            .iter()
+            .find(|migration| migration.from == header.version)
+            .ok_or_else(|| {
+                ClogError::CorruptHeader(format!("no migration from v{}", header.version))
+            })?;
+        (migration.apply)(header, metadata, base64_key)?;
+        header.version = migration.from + 1;
+    }
+    Ok(())
+}
+
+/// copies the clogfile to `<name>.v<version>.bak` unless such a backup already exists
+fn backup(clogfile_path: &PathBuf, version: u16) -> Result<()> {
+    let backup_path = sibling_path(clogfile_path, &format!("v{}.bak", version));
+    if backup_path.exists() {
+        return Ok(());
+    }
+
+    fs::copy(clogfile_path, &backup_path)?;
+    open_file_read(&backup_path)?.sync_all()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::backend::{
+        config::DEFAULT_USER,
+        session::Clog,
+        test_utils::{LEGACY_CREATED_AT, make_v1_clogfile, make_v2_clogfile, temp_clogfile},
+        user::utils::derive_key::{Kdf, LEGACY_KDF},
+    };
+
+    #[test]
+    fn v1_clogfile_is_upgraded_on_open() {
+        let path = temp_clogfile("migrate_v1");
+        make_v1_clogfile("pw", &path);
+        let original = fs::read(&path).unwrap();
+        let today = Local::now().format("%d/%m/%Y").to_string();
+
+        let mut clog = Clog::open(&path, "pw").unwrap();
+        assert_eq!(
+            parse_header_from_file(&path).unwrap().version,
+            FORMAT_VERSION
+        );
+        assert_eq!(clog.read_file(&today, "Welcome").unwrap(), "legacy welcome");
+
+        // entries got a full timestamp in the local timezone and a sequence number
+        let welcome = &clog.list().unwrap()[0];
+        let created_at = DateTime::parse_from_rfc3339(&welcome.created_at).unwrap();
+        let legacy_time = NaiveTime::parse_from_str(LEGACY_CREATED_AT, LEGACY_TIME_FORMAT);
+        assert_eq!(
+            created_at.naive_local(),
+            Local::now().date_naive().and_time(legacy_time.unwrap())
+        );
+        assert_eq!(welcome.modified_at, welcome.created_at);
+        assert_eq!(welcome.seq, 0);
+
+        // the backup is the untouched original
+        let backup_path = sibling_path(&path, "v1.bak");
+        assert_eq!(fs::read(&backup_path).unwrap(), original);
+
+        // and the upgraded file keeps working
+        clog.add_file("second", "added later").unwrap();
+        clog.edit_file(&today, "second", "edited later").unwrap();
+        let clog = Clog::open(&path, "pw").unwrap();
+        assert_eq!(clog.read_file(&today, "Welcome").unwrap(), "legacy welcome");
+        assert_eq!(clog.read_file(&today, "second").unwrap(), "edited later");
+        assert_eq!(clog.list().unwrap()[1].seq, 1);
+        let (_, base64_key) = Metadata::unlock_key_from_file(&path, "pw").unwrap();
+        assert!(!migrate_if_needed(&path, &base64_key).unwrap());
+    }
+
+    #[test]
+    fn v2_clogfile_gets_a_key_slot() {
+        let path = temp_clogfile("migrate_v2");
+        make_v2_clogfile("pw", &path);
+        let today = Local::now().format("%d/%m/%Y").to_string();
+
+        let mut clog = Clog::open(&path, "pw").unwrap();
+        let header = parse_header_from_file(&path).unwrap();
+        assert_eq!(header.version, FORMAT_VERSION);
+        assert_eq!(header.key_slots.len(), 1);
+        assert!(!header.key_slots[0].is_legacy());
+        assert_eq!(clog.user(), DEFAULT_USER);
+        assert_eq!(clog.read_file(&today, "Welcome").unwrap(), "v2 welcome");
+        assert!(sibling_path(&path, "v2.bak").exists());
+
+        // which keeps its PBKDF2 key until it is rekeyed
+        assert_eq!(header.key_slots[0].kdf, LEGACY_KDF);
+        assert!(matches!(
+            clog.rekey_kdf("wrong", Kdf::default()),
+            Err(ClogError::WrongPassword)
+        ));
+        clog.rekey_kdf("pw", Kdf::default()).unwrap();
+        let header = parse_header_from_file(&path).unwrap();
+        assert_eq!(header.key_slots[0].kdf, Kdf::default());
+
+        // the old password now unlocks a master key that other users can share
+        clog.add_user("alice", "alice pw").unwrap();
+        let alice = Clog::open(&path, "alice pw").unwrap();
+        assert_eq!(alice.read_file(&today, "Welcome").unwrap(), "v2 welcome");
+        assert!(matches!(
+            Clog::open(&path, "wrong"),
+            Err(ClogError::WrongPassword)
+        ));
+    }
+
+    #[test]
+    fn failed_migration_leaves_file_untouched() {
+        let path = temp_clogfile("migrate_wrong_password");
+        make_v1_clogfile("pw", &path);
+        let original = fs::read(&path).unwrap();
+
+        assert!(matches!(
+            Clog::open(&path, "wrong"),
+            Err(ClogError::WrongPassword)
+        ));
+        assert_eq!(fs::read(&path).unwrap(), original);
+        assert!(!sibling_path(&path, "v1.bak").exists());
+    }
+
+    #[test]
+    fn v6_folders_get_their_kind() {
+        use crate::backend::clock::SystemClock;
+
+        let mut metadata = Metadata::new(&SystemClock);
+        let mut projects = Folder::new(FolderKind::Dated);
+        projects
+            .folders
+            .insert("ideas".to_string(), Folder::new(FolderKind::Dated));
+        metadata.folders.insert("projects".to_string(), projects);
+        metadata
+            .folders
+            .insert("25/05/2025".to_string(), Folder::new(FolderKind::Named));
+
+        let mut header = Header::new("", Vec::new());
+        v6_to_v7(&mut header, &mut metadata, &mut String::new()).unwrap();
+        assert_eq!(
+            metadata.get_folder("25/05/2025").unwrap().kind,
+            FolderKind::Dated
+        );
+        assert_eq!(
+            metadata.get_folder("projects").unwrap().kind,
+            FolderKind::Named
+        );
+        assert_eq!(
+            metadata.get_folder("projects/ideas").unwrap().kind,
+            FolderKind::Named
+        );
+    }
+}
@@ -1,6 +1,14 @@
+mod clock;
 mod config;
+pub mod error;
 mod file_operations;
 mod header;
 pub mod main;
 mod metadata;
+mod migration;
+pub mod recover;
+pub mod session;
+#[cfg(test)]
+mod test_utils;
 mod user;
+pub mod verify;
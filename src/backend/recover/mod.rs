@@ -0,0 +1,430 @@
+use super::{
+    error::{ClogError, Result},
+    file_operations::{
+        content::decrypt_bytes,
+        lock::{LockPolicy, lock_exclusive, lock_shared},
+        rewrite::{EntryCiphertext, build_clogfile},
+        utils::write_file_atomically,
+    },
+    header::utils::{
+        HEADER_LENGTH, Header, find_headers, parse_header_from_bytes, parse_header_from_file,
+    },
+    metadata::{
+        file::{BlobId, File},
+        metadata::Metadata,
+    },
+    migration::upgrade,
+    user::key_slot::unlock_master_key,
+};
+use std::{fmt, fs, io::ErrorKind, path::PathBuf};
+
+/// What [`recover`] salvaged from a damaged clogfile.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RecoveryReport {
+    /// whether the header at the start of the file could still be parsed
+    pub header_intact: bool,
+    /// how many metadata records could be decrypted, the one the header points at along
+    /// with older ones and any an interrupted write left past it
+    pub metadata_records: usize,
+    /// (folder,name) of every entry recovered with its latest content
+    pub recovered: Vec<(String, String)>,
+    /// (folder,name) of every entry whose latest content was damaged, recovered with the
+    /// content an older metadata record knew
+    pub rolled_back: Vec<(String, String)>,
+    /// (folder,name) of every entry none of whose contents could be recovered
+    pub lost: Vec<(String, String)>,
+    /// how many kept versions were dropped as their ciphertext was damaged
+    pub lost_versions: usize,
+}
+
+/// One metadata record that decrypted, along with the header pointing at it.
+struct Record {
+    header: Header,
+    metadata: Metadata,
+}
+
+/// What could be carved out of the clogfile for one entry.
+struct Salvaged {
+    /// the entry without its damaged kept versions
+    file: File,
+    /// every ciphertext of `file`
+    ciphertexts: Vec<(BlobId, Vec<u8>)>,
+    lost_versions: usize,
+}
+
+/// Salvages whatever it can of the clogfile at `damaged_path` into a new clogfile at
+/// `recovered_path`, leaving the damaged one untouched.
+///
+/// The key slots come from the header, or from the header snapshot before any metadata
+/// record if the header is damaged. The metadata record the header points at decides
+/// which folders and entries there are, or the newest one that decrypts if that one is
+/// lost, such as one an interrupted write left past the end of the journal. Every entry
+/// is carved out by the offsets it records and kept only if it authenticates; if its
+/// latest content doesn't, older records are searched for an earlier content that does.
+///
+/// Fails if `recovered_path` exists, if no header or snapshot is left, if `password`
+/// opens none of their key slots, or if no metadata record decrypts. The single slot of
+/// v1 and v2 clogfiles takes any password, there no record decrypting gives
+/// [`ClogError::WrongPassword`].
+pub fn recover(
+    damaged_path: &PathBuf,
+    password: &str,
+    recovered_path: &PathBuf,
+) -> Result<RecoveryReport> {
+    let bytes = {
+        let _lock = lock_shared(damaged_path, LockPolicy::default())?;
+        fs::read(damaged_path)?
+    };
+    let mut report = RecoveryReport::default();
+
+    // 1. Find the header and every snapshot that points at the record right after it
+    let primary = parse_header_from_file(damaged_path).ok();
+    report.header_intact = primary.is_some();
+    let mut snapshots: Vec<Header> = find_headers(&bytes)
+        .into_iter()
+        .filter(|&offset| offset > 0)
+        .filter_map(|offset| {
+            parse_header_from_bytes(&bytes[offset..])
+                .ok()
+                .filter(|header| header.metadata_offset == offset + HEADER_LENGTH)
+        })
+        .collect();
+    // newest first
+    snapshots.reverse();
+    let headers: Vec<Header> = primary.into_iter().chain(snapshots).collect();
+    if headers.is_empty() {
+        return Err(ClogError::CorruptHeader(
+            "neither the header nor any header snapshot is readable".to_string(),
+        ));
+    }
+
+    // 2. Unlock the master key with the first set of key slots that opens
+    let (key_header, mut base64_key) = unlock_any(&headers, password)?;
+
+    // 3. Decrypt every metadata record, the one the header points at first
+    let mut records: Vec<Record> = Vec::new();
+    for header in &headers {
+        if records
+            .iter()
+            .any(|record| record.header.metadata_offset == header.metadata_offset)
+        {
+            continue;
+        }
+        let Some(stored) = header
+            .metadata_offset
+            .checked_add(header.metadata_length)
+            .and_then(|end| bytes.get(header.metadata_offset..end))
+        else {
+            continue;
+        };
+        let metadata = header.decode_blob(stored.to_vec()).and_then(|ciphertext| {
+            Metadata::parse_encrypted_metadata(&ciphertext, &base64_key, &header.base64_nonce)
+        });
+        if let Ok(metadata) = metadata {
+            records.push(Record {
+                header: header.clone(),
+                metadata,
+            });
+        }
+    }
+    report.metadata_records = records.len();
+    let Some((newest, older)) = records.split_first() else {
+        // a legacy slot takes any password, only the metadata tells it was wrong
+        if key_header.key_slots.iter().any(|slot| slot.is_legacy()) {
+            return Err(ClogError::WrongPassword);
+        }
+        return Err(ClogError::CorruptMetadata(
+            "no metadata record could be decrypted".to_string(),
+        ));
+    };
+
+    // 4. Carve out every entry the newest record knows, falling back to older records
+    let mut metadata = newest.metadata.clone();
+    let mut entries: Vec<EntryCiphertext> = Vec::new();
+    let names: Vec<(String, String)> = newest
+        .metadata
+        .all_folders()
+        .into_iter()
+        .flat_map(|(foldername, folder)| {
+            folder
+                .files
+                .keys()
+                .map(move |filename| (foldername.clone(), filename.clone()))
+        })
+        .collect();
+
+    for (foldername, filename) in names {
+        let latest = newest.metadata.get_file(&foldername, &filename)?;
+        let earlier = older.iter().filter_map(|record| {
+            record
+                .metadata
+                .get_file(&foldername, &filename)
+                .ok()
+                .filter(|file| file.seq == latest.seq)
+                .map(|file| (record, file))
+        });
+        let salvaged = std::iter::once((newest, latest))
+            .chain(earlier)
+            .enumerate()
+            .find_map(|(age, (record, file))| {
+                salvage(&bytes, &record.header, file).map(|salvaged| (age, salvaged))
+            });
+
+        let name = (foldername.clone(), filename.clone());
+        let Some((age, salvaged)) = salvaged else {
+            metadata
+                .get_folder_mut(&foldername)?
+                .files
+                .remove(&filename);
+            report.lost.push(name);
+            continue;
+        };
+        match age {
+            0 => report.recovered.push(name),
+            _ => report.rolled_back.push(name),
+        }
+        report.lost_versions += salvaged.lost_versions;
+        *metadata.get_file_mut(&foldername, &filename)? = salvaged.file;
+        entries.extend(salvaged.ciphertexts.into_iter().map(|(blob, ciphertext)| {
+            EntryCiphertext {
+                foldername: foldername.clone(),
+                filename: filename.clone(),
+                blob,
+                ciphertext,
+            }
+        }));
+    }
+
+    // 5. Write everything salvaged into a new clogfile in the current format
+    let mut header = key_header.clone();
+    upgrade(&mut header, &mut metadata, &mut base64_key)?;
+    let recovered = build_clogfile(&header, &mut metadata, &entries, &base64_key)?;
+
+    let _lock = lock_exclusive(recovered_path, LockPolicy::default())?;
+    if recovered_path.exists() {
+        return Err(ClogError::Io(ErrorKind::AlreadyExists.into()));
+    }
+    write_file_atomically(recovered_path, &recovered)?;
+
+    for list in [
+        &mut report.recovered,
+        &mut report.rolled_back,
+        &mut report.lost,
+    ] {
+        list.sort();
+    }
+    Ok(report)
+}
+
+/// master key from the first of `headers` whose key slots `password` opens, along with
+/// that header; slots already tried are skipped as unlocking is slow on purpose
+fn unlock_any<'a>(headers: &'a [Header], password: &str) -> Result<(&'a Header, String)> {
+    let mut tried: Vec<Vec<&Vec<u8>>> = Vec::new();
+    let mut error = ClogError::WrongPassword;
+    for header in headers {
+        let slots: Vec<&Vec<u8>> = header
+            .key_slots
+            .iter()
+            .map(|slot| &slot.wrapped_key)
+            .collect();
+        if tried.contains(&slots) {
+            continue;
+        }
+
+        match unlock_master_key(&header.key_slots, password) {
+            Ok((_, base64_key)) => return Ok((header, base64_key)),
+            Err(err) => error = err,
+        }
+        tried.push(slots);
+    }
+    Err(error)
+}
+
+/// Carves the ciphertexts of `file` out of `bytes`, laid out as `header` describes.
+///
+/// Gives `None` if the current content fails authentication, damaged kept versions
+/// are dropped.
+fn salvage(bytes: &[u8], header: &Header, file: &File) -> Option<Salvaged> {
+    let carve = |blob: BlobId| {
+        let (base64_key, base64_nonce, offset, length) = file.blob_parameters(blob)?;
+        let stored = bytes.get(offset..offset.checked_add(length)?)?;
+        let ciphertext = header.decode_blob(stored.to_vec()).ok()?;
+        decrypt_bytes(&ciphertext, base64_key, base64_nonce).ok()?;
+        Some((blob, ciphertext))
+    };
+
+    let mut file = file.clone();
+    let mut ciphertexts = Vec::new();
+    let mut damaged_versions = Vec::new();
+    for blob in file.blobs() {
+        match (carve(blob), blob.version) {
+            (Some(carved), _) => ciphertexts.push(carved),
+            (None, None) => return None,
+            (None, Some(number)) => damaged_versions.push(number),
+        }
+    }
+
+    ciphertexts.retain(|(blob, _)| {
+        blob.version
+            .is_none_or(|number| !damaged_versions.contains(&number))
+    });
+    file.versions
+        .retain(|version| !damaged_versions.contains(&version.number));
+    damaged_versions.dedup();
+    Some(Salvaged {
+        file,
+        ciphertexts,
+        lost_versions: damaged_versions.len(),
+    })
+}
+
+impl fmt::Display for RecoveryReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.header_intact {
+            true => write!(f, "header intact")?,
+            false => write!(f, "header damaged")?,
+        }
+        write!(
+            f,
+            ", {} metadata records readable, {} entries recovered, {} rolled back, {} lost, {} versions lost",
+            self.metadata_records,
+            self.recovered.len(),
+            self.rolled_back.len(),
+            self.lost.len(),
+            self.lost_versions
+        )?;
+
+        for (label, entries) in [("rolled back", &self.rolled_back), ("lost", &self.lost)] {
+            for (folder, name) in entries {
+                write!(f, "\n- {}: file '{}' in folder '{}'", label, name, folder)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::backend::{
+        clock::FixedClock,
+        file_operations::utils::make_new_clogfile,
+        session::Clog,
+        test_utils::{TEST_KDF, make_v1_clogfile, temp_clogfile},
+        verify::verify,
+    };
+    use std::sync::Arc;
+
+    fn new_clog(name: &str) -> (PathBuf, Clog) {
+        let path = temp_clogfile(name);
+        let clock = Arc::new(FixedClock::new(
+            chrono::DateTime::parse_from_rfc3339("2025-05-25T10:00:00+02:00").unwrap(),
+        ));
+        make_new_clogfile("pw", &path, TEST_KDF, &*clock).unwrap();
+        let clog = Clog::open(&path, "pw").unwrap().with_clock(clock);
+        (path, clog)
+    }
+
+    fn damage(path: &PathBuf, range: std::ops::Range<usize>) {
+        let mut bytes = fs::read(path).unwrap();
+        for byte in &mut bytes[range] {
+            *byte ^= 0xff;
+        }
+        fs::write(path, bytes).unwrap();
+    }
+
+    #[test]
+    fn recover_reads_the_header_snapshots_when_the_header_is_destroyed() {
+        let (path, mut clog) = new_clog("recover_header");
+        clog.set_version_limit(1).unwrap();
+        clog.add_file("a", "one").unwrap();
+        clog.edit_file("25/05/2025", "a", "two").unwrap();
+        clog.add_file("b", "bee").unwrap();
+
+        // an interrupted write appended a record the pointer never moved to
+        let committed = fs::read(&path).unwrap()[..HEADER_LENGTH].to_vec();
+        clog.append_to_file("25/05/2025", "b", ", appended")
+            .unwrap();
+        drop(clog);
+        let mut bytes = fs::read(&path).unwrap();
+        bytes[..HEADER_LENGTH].copy_from_slice(&committed);
+        fs::write(&path, &bytes).unwrap();
+
+        damage(&path, 0..HEADER_LENGTH);
+        assert!(Clog::open(&path, "pw").is_err());
+        let recovered_path = temp_clogfile("recover_header_out");
+        assert!(matches!(
+            recover(&path, "not pw", &recovered_path),
+            Err(ClogError::WrongPassword)
+        ));
+
+        let report = recover(&path, "pw", &recovered_path).unwrap();
+        assert!(!report.header_intact);
+        assert!(report.metadata_records > 1);
+        let names = |entries: &[&str]| {
+            entries
+                .iter()
+                .map(|name| ("25/05/2025".to_string(), name.to_string()))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(report.recovered, names(&["Welcome", "a", "b"]));
+        assert!(report.lost.is_empty() && report.rolled_back.is_empty());
+
+        // the newest record wins, kept versions come along
+        let clog = Clog::open(&recovered_path, "pw").unwrap();
+        assert_eq!(clog.read_file("25/05/2025", "b").unwrap(), "bee, appended");
+        assert_eq!(clog.read_file("25/05/2025", "a").unwrap(), "two");
+        assert_eq!(clog.get_version("25/05/2025", "a", 1).unwrap(), "one");
+        assert!(verify(&recovered_path, "pw").unwrap().is_healthy());
+        assert!(matches!(
+            recover(&path, "pw", &recovered_path),
+            Err(ClogError::Io(_))
+        ));
+    }
+
+    #[test]
+    fn recover_tells_a_wrong_password_on_legacy_files() {
+        let path = temp_clogfile("recover_v1");
+        make_v1_clogfile("pw", &path);
+        let recovered_path = temp_clogfile("recover_v1_out");
+
+        assert!(matches!(
+            recover(&path, "not pw", &recovered_path),
+            Err(ClogError::WrongPassword)
+        ));
+        let report = recover(&path, "pw", &recovered_path).unwrap();
+        assert_eq!(report.recovered.len(), 1);
+        assert!(verify(&recovered_path, "pw").unwrap().is_healthy());
+    }
+
+    #[test]
+    fn recover_rolls_back_damaged_entries_and_leaves_out_lost_ones() {
+        let (path, mut clog) = new_clog("recover_entries");
+        clog.add_file("a", "one").unwrap();
+        clog.add_file("b", "only").unwrap();
+        clog.edit_file("25/05/2025", "a", "two").unwrap();
+        let a = clog.metadata().get_file("25/05/2025", "a").unwrap();
+        let b = clog.metadata().get_file("25/05/2025", "b").unwrap();
+        let (a_at, b_at) = (a.offset, b.offset);
+        drop(clog);
+
+        // no version history, only the older metadata record still knows "one"
+        damage(&path, a_at..a_at + 1);
+        damage(&path, b_at..b_at + 1);
+        let recovered_path = temp_clogfile("recover_entries_out");
+        let report = recover(&path, "pw", &recovered_path).unwrap();
+        assert!(report.header_intact);
+        let entry = |name: &str| ("25/05/2025".to_string(), name.to_string());
+        assert_eq!(report.recovered, [entry("Welcome")]);
+        assert_eq!(report.rolled_back, [entry("a")]);
+        assert_eq!(report.lost, [entry("b")]);
+        assert!(report.to_string().contains("lost: file 'b'"));
+
+        let clog = Clog::open(&recovered_path, "pw").unwrap();
+        assert_eq!(clog.read_file("25/05/2025", "a").unwrap(), "one");
+        assert!(matches!(
+            clog.read_file("25/05/2025", "b"),
+            Err(ClogError::NotFound { .. })
+        ));
+    }
+}
@@ -0,0 +1,1884 @@
+pub use super::{
+    clock::{Clock, FixedClock, SystemClock},
+    file_operations::lock::LockPolicy,
+    metadata::{
+        audit::{AuditAction, AuditRecord},
+        folder::FolderKind,
+        path::ClogPath,
+        rollover::{RolloverReport, dated_folder_name},
+    },
+    user::utils::derive_key::Kdf,
+};
+use super::{
+    config::{FORMAT_VERSION, KEY_SLOTS},
+    error::{ClogError, Result},
+    file_operations::{
+        content::{
+            add_file_with_content, append_to_file_with_content, decrypt_content_from_file,
+            decrypt_version_from_file, delete_file_with_content, edit_file_with_content,
+        },
+        lock::{lock_exclusive, lock_shared},
+        rewrite::{build_clogfile, read_entry_ciphertexts},
+        utils::{open_file_read_write, wipe_region, with_working_copy},
+    },
+    header::utils::{HEADER_LENGTH, find_headers, parse_header_from_file},
+    metadata::metadata::Metadata,
+    migration::migrate_if_needed,
+    user::key_slot::{KeySlot, unlock_master_key},
+};
+use std::{
+    borrow::Cow,
+    fs,
+    io::{Read, Seek, SeekFrom},
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+/// A single entry as reported by [`Clog::list`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EntryInfo {
+    pub folder: String,
+    pub name: String,
+    /// creation order within the clogfile
+    pub seq: u64,
+    /// RFC 3339 with offset
+    pub created_at: String,
+    /// RFC 3339 with offset
+    pub modified_at: String,
+}
+
+/// One content of an entry as reported by [`Clog::list_versions`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VersionInfo {
+    /// counts up with every edit, the first content is 1
+    pub number: u64,
+    /// RFC 3339 with offset, when this content was written
+    pub modified_at: String,
+    /// whether this is what the entry holds now
+    pub current: bool,
+}
+
+/// What [`Clog::ls`] found in a folder.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FolderListing {
+    /// names of the folders inside, sorted
+    pub folders: Vec<String>,
+    /// names of the entries inside, sorted
+    pub files: Vec<String>,
+}
+
+/// An open clogfile.
+///
+/// The key is derived from the password once in [`Clog::open`] and the decrypted
+/// metadata is kept in memory, so every later call only touches the entries it needs.
+/// Every mutation only appends to the clogfile: new ciphertext first, then a new
+/// metadata record, and the header is pointed at that record last. A crash before
+/// that leaves the previous state in place, and [`Clog::compact`] reclaims whatever
+/// earlier writes left behind.
+///
+/// Reads take a shared lock and writes an exclusive one, so several processes can
+/// use the same clogfile. The cached metadata is reloaded whenever another process
+/// changed the file in between.
+pub struct Clog {
+    path: PathBuf,
+    /// user whose key slot the password unlocked
+    user: String,
+    base64_key: String,
+    metadata: Metadata,
+    /// metadata nonce of the clogfile the cached metadata was read from,
+    /// every write changes it
+    metadata_nonce: String,
+    lock_policy: LockPolicy,
+    /// reason given to [`Clog::override_read_only`] while it runs
+    read_only_override: Option<String>,
+    /// gives today's folder and the timestamps of new entries
+    clock: Arc<dyn Clock>,
+}
+
+impl Clog {
+    /// Derives the key from `password` and decrypts the metadata of the clogfile at `path`,
+    /// waiting for any other process that is writing to it.
+    ///
+    /// Clogfiles written in an older format are upgraded first, keeping a `.bak` copy.
+    pub fn open(path: impl AsRef<Path>, password: &str) -> Result<Self> {
+        Self::open_with_lock_policy(path, password, LockPolicy::default())
+    }
+
+    /// Like [`Clog::open`], but `lock_policy` decides what every call does when
+    /// another process holds the lock.
+    pub fn open_with_lock_policy(
+        path: impl AsRef<Path>,
+        password: &str,
+        lock_policy: LockPolicy,
+    ) -> Result<Self> {
+        let path = path.as_ref().to_path_buf();
+
+        let (mut user, mut base64_key, needs_migration) = {
+            let _lock = lock_shared(&path, lock_policy)?;
+            let header = parse_header_from_file(&path)?;
+            let (user, base64_key) = unlock_master_key(&header.key_slots, password)?;
+            (user, base64_key, header.version != FORMAT_VERSION)
+        };
+
+        // migrating can move the metadata to a new key, unlock again afterwards
+        if needs_migration {
+            let _lock = lock_exclusive(&path, lock_policy)?;
+            if migrate_if_needed(&path, &base64_key)? {
+                (user, base64_key) = Metadata::unlock_key_from_file(&path, password)?;
+            }
+        }
+
+        let _lock = lock_shared(&path, lock_policy)?;
+        let metadata_nonce = parse_header_from_file(&path)?.base64_nonce;
+        let metadata = Metadata::extract_metadata_with_key(&path, &base64_key)?;
+
+        Ok(Clog {
+            path,
+            user,
+            base64_key,
+            metadata,
+            metadata_nonce,
+            lock_policy,
+            read_only_override: None,
+            clock: Arc::new(SystemClock),
+        })
+    }
+
+    /// Uses `clock` instead of the system time to decide what today is and to
+    /// timestamp new entries.
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// The user whose password opened this session.
+    pub fn user(&self) -> &str {
+        &self.user
+    }
+
+    /// Adds a new entry to today's folder, creating the folder if needed.
+    ///
+    /// Once today's folder is sealed it only takes new entries through
+    /// [`Clog::override_read_only`].
+    pub fn add_file(&mut self, filename: &str, content: &str) -> Result<()> {
+        let user = self.user.clone();
+        let reason = self.read_only_override.clone();
+        let clock = Arc::clone(&self.clock);
+        let foldername = dated_folder_name(clock.today());
+
+        self.mutate(|metadata, base64_key, path| {
+            if !metadata.folders.contains_key(&foldername) {
+                metadata.add_latest_folder(&*clock);
+            }
+
+            // if file does exists return error
+            if metadata
+                .get_folder(&foldername)?
+                .files
+                .contains_key(filename)
+            {
+                return Err(ClogError::AlreadyExists {
+                    folder: foldername.clone(),
+                    file: filename.to_string(),
+                });
+            }
+            metadata.authorize_change(
+                &user,
+                AuditAction::Add,
+                &foldername,
+                filename,
+                reason.as_deref(),
+                &*clock,
+            )?;
+
+            add_file_with_content(
+                metadata,
+                base64_key,
+                &foldername,
+                filename,
+                content,
+                path,
+                &*clock,
+            )
+        })
+    }
+
+    /// Adds a new entry to the existing folder `foldername`, which can be any folder
+    /// path such as `projects/clog/ideas`.
+    ///
+    /// Read-only folders only take new entries through [`Clog::override_read_only`].
+    pub fn add_file_to(&mut self, foldername: &str, filename: &str, content: &str) -> Result<()> {
+        let user = self.user.clone();
+        let reason = self.read_only_override.clone();
+        let clock = Arc::clone(&self.clock);
+
+        self.mutate(|metadata, base64_key, path| {
+            if metadata
+                .get_folder(foldername)?
+                .files
+                .contains_key(filename)
+            {
+                return Err(ClogError::AlreadyExists {
+                    folder: foldername.to_string(),
+                    file: filename.to_string(),
+                });
+            }
+            metadata.authorize_change(
+                &user,
+                AuditAction::Add,
+                foldername,
+                filename,
+                reason.as_deref(),
+                &*clock,
+            )?;
+
+            add_file_with_content(
+                metadata, base64_key, foldername, filename, content, path, &*clock,
+            )
+        })
+    }
+
+    /// Creates the empty folder `path`, e.g. `projects/clog` or `25/05/2025/ideas`.
+    ///
+    /// The folder holding it must exist already. Creating a folder inside a read-only
+    /// one needs [`Clog::override_read_only`].
+    pub fn mkdir(&mut self, path: &str) -> Result<()> {
+        let path = ClogPath::parse(path)?;
+        let user = self.user.clone();
+        let reason = self.read_only_override.clone();
+        let clock = Arc::clone(&self.clock);
+
+        self.mutate(|metadata, base64_key, clogfile_path| {
+            if let Some((parent, name)) = path.split_last()
+                && !parent.is_root()
+            {
+                metadata.authorize_change(
+                    &user,
+                    AuditAction::Mkdir,
+                    &parent.to_string(),
+                    name,
+                    reason.as_deref(),
+                    &*clock,
+                )?;
+            }
+            metadata.make_folder(&path)?;
+            metadata.update_metadata_in_file(clogfile_path, base64_key)
+        })
+    }
+
+    /// Removes the folder `path`, failing with [`ClogError::FolderNotEmpty`] while it
+    /// still holds entries or folders.
+    ///
+    /// Read-only folders, or folders inside one, need [`Clog::override_read_only`].
+    pub fn rmdir(&mut self, path: &str) -> Result<()> {
+        let path = ClogPath::parse(path)?;
+        let user = self.user.clone();
+        let reason = self.read_only_override.clone();
+        let clock = Arc::clone(&self.clock);
+
+        self.mutate(|metadata, base64_key, clogfile_path| {
+            metadata.authorize_change(
+                &user,
+                AuditAction::Rmdir,
+                &path.to_string(),
+                "",
+                reason.as_deref(),
+                &*clock,
+            )?;
+            metadata.remove_folder(&path)?;
+            metadata.update_metadata_in_file(clogfile_path, base64_key)
+        })
+    }
+
+    /// Seals the folder `path`, after which it and every folder inside it can only be
+    /// changed through [`Clog::override_read_only`].
+    ///
+    /// Named folders are only ever sealed this way, day folders are sealed by
+    /// [`Clog::roll_over`] too.
+    pub fn seal_folder(&mut self, path: &str) -> Result<()> {
+        self.mutate(|metadata, base64_key, clogfile_path| {
+            metadata.get_folder_mut(path)?.make_read_only();
+            metadata.update_metadata_in_file(clogfile_path, base64_key)
+        })
+    }
+
+    /// Whether `path` is a day folder or one named by the user.
+    pub fn folder_kind(&self, path: &str) -> Result<FolderKind> {
+        let _lock = lock_shared(&self.path, self.lock_policy)?;
+        Ok(self.current_metadata()?.get_folder(path)?.kind)
+    }
+
+    /// Lists the folders and entries directly inside `path`, or the top-level folders
+    /// for the root `""`.
+    pub fn ls(&self, path: &str) -> Result<FolderListing> {
+        let _lock = lock_shared(&self.path, self.lock_policy)?;
+        let metadata = self.current_metadata()?;
+        let mut listing = FolderListing::default();
+        if ClogPath::parse(path)?.is_root() {
+            listing.folders = metadata.folders.keys().cloned().collect();
+        } else {
+            let folder = metadata.get_folder(path)?;
+            listing.folders = folder.folders.keys().cloned().collect();
+            listing.files = folder.files.keys().cloned().collect();
+        }
+
+        listing.folders.sort();
+        listing.files.sort();
+        Ok(listing)
+    }
+
+    /// Seals every past folder and creates today's folder, writing the clogfile only
+    /// if something changed.
+    pub fn roll_over(&mut self) -> Result<RolloverReport> {
+        let today = self.clock.today();
+        if self.metadata.clone().roll_over(today).is_empty() {
+            return Ok(RolloverReport::default());
+        }
+
+        self.mutate(|metadata, base64_key, path| {
+            // decide again on the metadata as it is on disk now
+            let report = metadata.roll_over(today);
+            if !report.is_empty() {
+                metadata.update_metadata_in_file(path, base64_key)?;
+            }
+            Ok(report)
+        })
+    }
+
+    /// Replaces the content of an existing entry.
+    ///
+    /// Entries in read-only folders can only be edited through [`Clog::override_read_only`].
+    pub fn edit_file(&mut self, foldername: &str, filename: &str, content: &str) -> Result<()> {
+        let user = self.user.clone();
+        let reason = self.read_only_override.clone();
+        let clock = Arc::clone(&self.clock);
+
+        self.mutate(|metadata, base64_key, path| {
+            metadata.get_file(foldername, filename)?;
+            metadata.authorize_change(
+                &user,
+                AuditAction::Edit,
+                foldername,
+                filename,
+                reason.as_deref(),
+                &*clock,
+            )?;
+            edit_file_with_content(
+                metadata, base64_key, foldername, filename, content, path, &*clock,
+            )
+        })
+    }
+
+    /// Adds `text` to the end of an entry as a chunk of its own, written after every other
+    /// entry so nothing stored after the entry has to move. Reads give the content
+    /// followed by every appended chunk.
+    ///
+    /// Entries in read-only folders can only be appended to through
+    /// [`Clog::override_read_only`].
+    pub fn append_to_file(&mut self, foldername: &str, filename: &str, text: &str) -> Result<()> {
+        let user = self.user.clone();
+        let reason = self.read_only_override.clone();
+        let clock = Arc::clone(&self.clock);
+
+        self.mutate(|metadata, base64_key, path| {
+            metadata.get_file(foldername, filename)?;
+            metadata.authorize_change(
+                &user,
+                AuditAction::Append,
+                foldername,
+                filename,
+                reason.as_deref(),
+                &*clock,
+            )?;
+            append_to_file_with_content(
+                metadata, base64_key, foldername, filename, text, path, &*clock,
+            )
+        })
+    }
+
+    /// Makes the content numbered `version` of an entry its current content again, as an
+    /// edit that is itself kept in the history.
+    ///
+    /// Entries in read-only folders can only be restored through [`Clog::override_read_only`].
+    pub fn restore_version(
+        &mut self,
+        foldername: &str,
+        filename: &str,
+        version: u64,
+    ) -> Result<()> {
+        let user = self.user.clone();
+        let reason = self.read_only_override.clone();
+        let clock = Arc::clone(&self.clock);
+
+        self.mutate(|metadata, base64_key, path| {
+            let content = decrypt_version_from_file(metadata, foldername, filename, version, path)?;
+            metadata.authorize_change(
+                &user,
+                AuditAction::Restore,
+                foldername,
+                filename,
+                reason.as_deref(),
+                &*clock,
+            )?;
+            edit_file_with_content(
+                metadata, base64_key, foldername, filename, &content, path, &*clock,
+            )
+        })
+    }
+
+    /// Every content of an entry still kept, oldest first and ending with the current one.
+    pub fn list_versions(&self, foldername: &str, filename: &str) -> Result<Vec<VersionInfo>> {
+        let _lock = lock_shared(&self.path, self.lock_policy)?;
+        let metadata = self.current_metadata()?;
+        let file = metadata.get_file(foldername, filename)?;
+        let mut versions: Vec<VersionInfo> = file
+            .versions
+            .iter()
+            .map(|version| VersionInfo {
+                number: version.number,
+                modified_at: version.modified_at.clone(),
+                current: false,
+            })
+            .collect();
+        versions.push(VersionInfo {
+            number: file.version,
+            modified_at: file.modified_at.clone(),
+            current: true,
+        });
+        Ok(versions)
+    }
+
+    /// Decrypts and returns the content numbered `version` of an entry, failing with
+    /// [`ClogError::VersionNotFound`] if it isn't kept.
+    pub fn get_version(&self, foldername: &str, filename: &str, version: u64) -> Result<String> {
+        let _lock = lock_shared(&self.path, self.lock_policy)?;
+        let metadata = self.current_metadata()?;
+        decrypt_version_from_file(&metadata, foldername, filename, version, &self.path)
+    }
+
+    /// Sets how many earlier contents every entry keeps, 0 turns the version history off.
+    /// Replaced contents then stay in the clogfile as dead bytes until [`Clog::compact`].
+    ///
+    /// Entries holding more versions drop the oldest ones the next time they are edited.
+    pub fn set_version_limit(&mut self, limit: usize) -> Result<()> {
+        self.mutate(|metadata, base64_key, path| {
+            metadata.version_limit = limit;
+            metadata.update_metadata_in_file(path, base64_key)
+        })
+    }
+
+    /// How many earlier contents every entry keeps.
+    pub fn version_limit(&self) -> Result<usize> {
+        let _lock = lock_shared(&self.path, self.lock_policy)?;
+        Ok(self.current_metadata()?.version_limit)
+    }
+
+    /// Removes an entry. Its ciphertexts stay in the clogfile as dead bytes until
+    /// [`Clog::compact`].
+    ///
+    /// With `wipe` the clogfile is compacted right away, and the replaced one is
+    /// overwritten with random bytes. That covers every ciphertext the entry ever had,
+    /// replaced contents included, and every older metadata record naming it, so none
+    /// of it lingers on disk. Entries in read-only folders can only be deleted through
+    /// [`Clog::override_read_only`].
+    pub fn delete_file(&mut self, foldername: &str, filename: &str, wipe: bool) -> Result<()> {
+        let user = self.user.clone();
+        let reason = self.read_only_override.clone();
+        let clock = Arc::clone(&self.clock);
+        let delete = |metadata: &mut Metadata, base64_key: &str, path: &PathBuf| {
+            metadata.get_file(foldername, filename)?;
+            metadata.authorize_change(
+                &user,
+                AuditAction::Delete,
+                foldername,
+                filename,
+                reason.as_deref(),
+                &*clock,
+            )?;
+            delete_file_with_content(metadata, base64_key, foldername, filename, path)
+        };
+
+        if !wipe {
+            return self.mutate(delete);
+        }
+        let clogfile_path = self.path.clone();
+        let mut old_file = self.mutate_with_working_copy(|metadata, base64_key, path| {
+            // hold on to the old clogfile, it is unlinked by the commit
+            let old_file = open_file_read_write(&clogfile_path)?;
+
+            delete(metadata, base64_key, path)?;
+            let header = parse_header_from_file(path)?;
+            let entries = read_entry_ciphertexts(path, &header, metadata)?;
+            fs::write(
+                path,
+                build_clogfile(&header, metadata, &entries, base64_key)?,
+            )?;
+            Ok(old_file)
+        })?;
+
+        let length = old_file.metadata()?.len() as usize;
+        wipe_region(&mut old_file, 0, length)
+    }
+
+    /// Renames an entry within its folder, failing with [`ClogError::AlreadyExists`] if
+    /// the new name is taken and with [`ClogError::InvalidPath`] if it is empty, `.`,
+    /// `..` or contains a `/`. Only the metadata is rewritten, the content stays as it is.
+    ///
+    /// Entries in read-only folders can only be renamed through [`Clog::override_read_only`].
+    pub fn rename_file(
+        &mut self,
+        foldername: &str,
+        old_filename: &str,
+        new_filename: &str,
+    ) -> Result<()> {
+        let user = self.user.clone();
+        let reason = self.read_only_override.clone();
+        let clock = Arc::clone(&self.clock);
+
+        self.mutate(|metadata, base64_key, path| {
+            metadata.get_file(foldername, old_filename)?;
+            metadata.authorize_change(
+                &user,
+                AuditAction::Rename,
+                foldername,
+                old_filename,
+                reason.as_deref(),
+                &*clock,
+            )?;
+            metadata.rename_file(foldername, old_filename, new_filename)?;
+            metadata.update_metadata_in_file(path, base64_key)
+        })
+    }
+
+    /// Moves an entry to another existing folder, failing with [`ClogError::AlreadyExists`]
+    /// if that folder already has an entry of that name. Only the metadata is rewritten,
+    /// the content stays as it is.
+    ///
+    /// If either folder is read-only the move needs [`Clog::override_read_only`].
+    pub fn move_file(
+        &mut self,
+        src_foldername: &str,
+        dst_foldername: &str,
+        filename: &str,
+    ) -> Result<()> {
+        let user = self.user.clone();
+        let reason = self.read_only_override.clone();
+        let clock = Arc::clone(&self.clock);
+
+        self.mutate(|metadata, base64_key, path| {
+            metadata.get_file(src_foldername, filename)?;
+            let mut foldernames = vec![src_foldername];
+            if dst_foldername != src_foldername {
+                foldernames.push(dst_foldername);
+            }
+            for foldername in foldernames {
+                metadata.authorize_change(
+                    &user,
+                    AuditAction::Move,
+                    foldername,
+                    filename,
+                    reason.as_deref(),
+                    &*clock,
+                )?;
+            }
+            metadata.move_file(src_foldername, dst_foldername, filename)?;
+            metadata.update_metadata_in_file(path, base64_key)
+        })
+    }
+
+    /// Runs `op` with read-only folders unlocked, for an administrator who really has to
+    /// fix an old entry. Every change `op` makes to a read-only folder is recorded with
+    /// `reason` and the session's user in the audit log, in the same write as the change.
+    pub fn override_read_only<T>(
+        &mut self,
+        reason: &str,
+        op: impl FnOnce(&mut Clog) -> Result<T>,
+    ) -> Result<T> {
+        let previous = self.read_only_override.replace(reason.to_string());
+        let result = op(self);
+        self.read_only_override = previous;
+        result
+    }
+
+    /// Every change made to a read-only folder, oldest first.
+    pub fn audit_log(&self) -> Result<Vec<AuditRecord>> {
+        let _lock = lock_shared(&self.path, self.lock_policy)?;
+        Ok(self.current_metadata()?.audit_log.clone())
+    }
+
+    /// Decrypts and returns the content of an entry.
+    pub fn read_file(&self, foldername: &str, filename: &str) -> Result<String> {
+        let _lock = lock_shared(&self.path, self.lock_policy)?;
+        let metadata = self.current_metadata()?;
+        decrypt_content_from_file(&metadata, foldername, filename, &self.path)
+    }
+
+    /// Lists every entry, sorted by folder and then by name.
+    pub fn list(&self) -> Result<Vec<EntryInfo>> {
+        let _lock = lock_shared(&self.path, self.lock_policy)?;
+        let metadata = self.current_metadata()?;
+        let mut entries: Vec<EntryInfo> = metadata
+            .all_folders()
+            .into_iter()
+            .flat_map(|(foldername, folder)| {
+                folder.files.iter().map(move |(filename, file)| EntryInfo {
+                    folder: foldername.clone(),
+                    name: filename.clone(),
+                    seq: file.seq,
+                    created_at: file.created_at.clone(),
+                    modified_at: file.modified_at.clone(),
+                })
+            })
+            .collect();
+
+        entries.sort_by(|a, b| (&a.folder, &a.name).cmp(&(&b.folder, &b.name)));
+        Ok(entries)
+    }
+
+    /// Replaces the password of the user that opened this session.
+    ///
+    /// Only that user's key slot is rewritten, the master key and every entry stay as
+    /// they are. The old header is overwritten with random bytes once the new clogfile
+    /// is in place, so the old slot can't be brute-forced with the old password later on.
+    pub fn change_password(&mut self, new_password: &str) -> Result<()> {
+        self.rewrap_own_key_slot(|slot| Ok((new_password, slot.kdf)))
+    }
+
+    /// Derives the key slot of the user that opened this session with `kdf` from now on,
+    /// e.g. to move a migrated clogfile from PBKDF2 to Argon2id.
+    ///
+    /// `password` must be that user's current password, it stays the same.
+    pub fn rekey_kdf(&mut self, password: &str, kdf: Kdf) -> Result<()> {
+        self.rewrap_own_key_slot(|slot| {
+            slot.unlock(password)?;
+            Ok((password, kdf))
+        })
+    }
+
+    /// Gives `user` their own `password` for this clogfile.
+    pub fn add_user(&mut self, user: &str, password: &str) -> Result<()> {
+        self.rewrite_key_slots(|key_slots, base64_key| {
+            if key_slots.iter().any(|slot| slot.user == user) {
+                return Err(ClogError::UserExists {
+                    user: user.to_string(),
+                });
+            }
+            if key_slots.len() >= KEY_SLOTS {
+                return Err(ClogError::KeySlotsFull);
+            }
+
+            key_slots.push(KeySlot::new(user, password, Kdf::default(), base64_key)?);
+            Ok(())
+        })
+    }
+
+    /// Removes the key slot of `user`, so their password no longer opens the clogfile.
+    ///
+    /// The master key stays the same, so this can't lock out someone who already
+    /// copied it out of an opened session.
+    pub fn remove_user(&mut self, user: &str) -> Result<()> {
+        self.rewrite_key_slots(|key_slots, _| {
+            let index = key_slots
+                .iter()
+                .position(|slot| slot.user == user)
+                .ok_or_else(|| ClogError::UserNotFound {
+                    user: user.to_string(),
+                })?;
+            if key_slots.len() == 1 {
+                return Err(ClogError::InvalidUser(
+                    "the last user can't be removed".to_string(),
+                ));
+            }
+
+            key_slots.remove(index);
+            Ok(())
+        })
+    }
+
+    /// Names of every user with a key slot.
+    pub fn users(&self) -> Result<Vec<String>> {
+        let _lock = lock_shared(&self.path, self.lock_policy)?;
+        let header = parse_header_from_file(&self.path)?;
+        Ok(header.key_slots.into_iter().map(|slot| slot.user).collect())
+    }
+
+    /// Rewrites the clogfile with every entry packed back to back, dropping the dead bytes
+    /// every write leaves behind. Returns how many bytes were freed.
+    pub fn compact(&mut self) -> Result<u64> {
+        self.mutate_with_working_copy(|metadata, base64_key, path| {
+            let old_length = fs::metadata(path)?.len();
+            let header = parse_header_from_file(path)?;
+            let entries = read_entry_ciphertexts(path, &header, metadata)?;
+            let bytes = build_clogfile(&header, metadata, &entries, base64_key)?;
+            fs::write(path, &bytes)?;
+            Ok(old_length.saturating_sub(bytes.len() as u64))
+        })
+    }
+
+    /// How many bytes of the clogfile hold nothing live anymore: replaced contents,
+    /// deleted entries, dropped versions and older metadata records. [`Clog::compact`]
+    /// frees them.
+    pub fn dead_bytes(&self) -> Result<u64> {
+        let _lock = lock_shared(&self.path, self.lock_policy)?;
+        let metadata = self.current_metadata()?;
+        let header = parse_header_from_file(&self.path)?;
+        let live = HEADER_LENGTH
+            + metadata.live_blob_bytes()
+            + header.snapshot_length()
+            + header.metadata_length;
+        Ok(fs::metadata(&self.path)?.len().saturating_sub(live as u64))
+    }
+
+    /// Syncs everything written so far to disk.
+    pub fn flush(&mut self) -> Result<()> {
+        open_file_read_write(&self.path)?.sync_all()?;
+        Ok(())
+    }
+
+    pub(crate) fn metadata(&self) -> &Metadata {
+        &self.metadata
+    }
+
+    /// Wraps the master key again in the key slot of this session's user, with the
+    /// password and kdf `pick` chooses based on the current slot.
+    fn rewrap_own_key_slot<'a>(
+        &mut self,
+        pick: impl FnOnce(&KeySlot) -> Result<(&'a str, Kdf)>,
+    ) -> Result<()> {
+        let user = self.user.clone();
+        self.rewrite_key_slots(|key_slots, base64_key| {
+            let slot = key_slots
+                .iter_mut()
+                .find(|slot| slot.user == user)
+                .ok_or_else(|| ClogError::UserNotFound { user: user.clone() })?;
+            let (password, kdf) = pick(slot)?;
+            *slot = KeySlot::new(&user, password, kdf, base64_key)?;
+            Ok(())
+        })
+    }
+
+    /// Lets `edit` change the key slots and rewrites the clogfile packed, as every header
+    /// snapshot holds the old slots. Then overwrites the header and every snapshot of the
+    /// replaced clogfile with random bytes so no stale slot lingers on disk.
+    fn rewrite_key_slots(
+        &mut self,
+        edit: impl FnOnce(&mut Vec<KeySlot>, &str) -> Result<()>,
+    ) -> Result<()> {
+        let clogfile_path = self.path.clone();
+
+        let mut old_file = self.mutate_with_working_copy(|metadata, base64_key, path| {
+            // hold on to the old clogfile, it is unlinked by the commit
+            let old_file = open_file_read_write(&clogfile_path)?;
+
+            let mut header = parse_header_from_file(path)?;
+            edit(&mut header.key_slots, base64_key)?;
+            let entries = read_entry_ciphertexts(path, &header, metadata)?;
+            fs::write(
+                path,
+                build_clogfile(&header, metadata, &entries, base64_key)?,
+            )?;
+            Ok(old_file)
+        })?;
+
+        let mut old_bytes = Vec::new();
+        old_file.seek(SeekFrom::Start(0))?;
+        old_file.read_to_end(&mut old_bytes)?;
+        for offset in find_headers(&old_bytes) {
+            let length = HEADER_LENGTH.min(old_bytes.len() - offset);
+            wipe_region(&mut old_file, offset, length)?;
+        }
+        Ok(())
+    }
+
+    /// The metadata matching the clogfile as it is on disk right now,
+    /// only decrypted again if another process wrote to it since it was cached.
+    /// Callers must hold a lock.
+    fn current_metadata(&self) -> Result<Cow<'_, Metadata>> {
+        if parse_header_from_file(&self.path)?.base64_nonce == self.metadata_nonce {
+            Ok(Cow::Borrowed(&self.metadata))
+        } else {
+            Metadata::extract_metadata_with_key(&self.path, &self.base64_key).map(Cow::Owned)
+        }
+    }
+
+    /// Runs `op` on a copy of the metadata against the clogfile itself, and only keeps the
+    /// copy once `op` committed it.
+    ///
+    /// `op` may only append to the clogfile, nothing readers can see changes until the
+    /// metadata written last points at what it appended. If `op` fails before that, the
+    /// appended bytes are cut off again.
+    fn mutate<T>(
+        &mut self,
+        op: impl FnOnce(&mut Metadata, &str, &PathBuf) -> Result<T>,
+    ) -> Result<T> {
+        let _lock = lock_exclusive(&self.path, self.lock_policy)?;
+        let mut metadata = self.current_metadata()?.into_owned();
+        let committed_nonce = parse_header_from_file(&self.path)?.base64_nonce;
+        let log_end = fs::metadata(&self.path)?.len();
+
+        let result = op(&mut metadata, &self.base64_key, &self.path);
+
+        if result.is_err()
+            && let Ok(header) = parse_header_from_file(&self.path)
+            && header.base64_nonce == committed_nonce
+        {
+            // best effort, whatever is left past the metadata is dead bytes anyway
+            let _ = open_file_read_write(&self.path).and_then(|file| Ok(file.set_len(log_end)?));
+        }
+        self.keep_committed(result.as_ref().ok().map(|_| metadata));
+        result
+    }
+
+    /// Like [`Clog::mutate`], but `op` runs against a working copy of the clogfile that
+    /// atomically replaces it, for changes that rewrite bytes in place such as the key
+    /// slots or a compaction.
+    fn mutate_with_working_copy<T>(
+        &mut self,
+        op: impl FnOnce(&mut Metadata, &str, &PathBuf) -> Result<T>,
+    ) -> Result<T> {
+        let _lock = lock_exclusive(&self.path, self.lock_policy)?;
+        let mut metadata = self.current_metadata()?.into_owned();
+
+        let result = with_working_copy(&self.path, |work_path| {
+            op(&mut metadata, &self.base64_key, work_path)
+        });
+
+        self.keep_committed(result.as_ref().ok().map(|_| metadata));
+        result
+    }
+
+    /// Caches the metadata a mutation committed, or whatever is on disk if it failed,
+    /// as the commit may have failed after the metadata was already in place.
+    /// Callers must hold the exclusive lock.
+    fn keep_committed(&mut self, committed: Option<Metadata>) {
+        let on_disk = match committed {
+            Some(metadata) => Ok(metadata),
+            None => Metadata::extract_metadata_with_key(&self.path, &self.base64_key),
+        };
+        if let (Ok(metadata), Ok(header)) = (on_disk, parse_header_from_file(&self.path)) {
+            self.metadata = metadata;
+            self.metadata_nonce = header.base64_nonce;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::backend::{
+        file_operations::utils::{make_new_clogfile, sibling_path, simulated_crash},
+        header::utils::{METADATA_POINTER, parse_header_from_bytes},
+        metadata::folder::Folder,
+        test_utils::{TEST_KDF, make_test_clogfile, temp_clogfile},
+    };
+    use chrono::Local;
+
+    #[test]
+    fn session_reads_back_its_own_writes() {
+        let path = temp_clogfile("session");
+        make_test_clogfile("pw", &path);
+        let today = Local::now().format("%d/%m/%Y").to_string();
+
+        let mut clog = Clog::open(&path, "pw").unwrap();
+        clog.add_file("first", "one").unwrap();
+        clog.add_file("second", "two").unwrap();
+        clog.edit_file(&today, "second", "two, edited").unwrap();
+        clog.flush().unwrap();
+
+        assert_eq!(clog.read_file(&today, "first").unwrap(), "one");
+        assert_eq!(clog.read_file(&today, "second").unwrap(), "two, edited");
+        let names: Vec<String> = clog
+            .list()
+            .unwrap()
+            .into_iter()
+            .map(|entry| entry.name)
+            .collect();
+        assert_eq!(names, ["Welcome", "first", "second"]);
+
+        // a fresh session sees the same state
+        let reopened = Clog::open(&path, "pw").unwrap();
+        assert_eq!(reopened.list().unwrap(), clog.list().unwrap());
+        assert_eq!(reopened.read_file(&today, "second").unwrap(), "two, edited");
+    }
+
+    #[test]
+    fn shrinking_writes_leave_dead_bytes_until_compacted() {
+        let path = temp_clogfile("session_shrink");
+        make_test_clogfile("pw", &path);
+        let today = Local::now().format("%d/%m/%Y").to_string();
+
+        let mut clog = Clog::open(&path, "pw").unwrap();
+        clog.add_file("long", &"x".repeat(4096)).unwrap();
+        clog.add_file("after", "stays readable").unwrap();
+        let dead_before_edit = clog.dead_bytes().unwrap();
+        clog.edit_file(&today, "long", "short").unwrap();
+
+        // the long ciphertext stays behind, next to the replaced metadata record
+        let dead = clog.dead_bytes().unwrap();
+        assert!(dead >= dead_before_edit + 4096);
+        assert!(clog.compact().unwrap() >= 4096);
+        assert_eq!(clog.dead_bytes().unwrap(), 0);
+
+        assert_eq!(clog.read_file(&today, "long").unwrap(), "short");
+        assert_eq!(clog.read_file(&today, "after").unwrap(), "stays readable");
+    }
+
+    #[test]
+    fn compact_drops_accumulated_garbage() {
+        let path = temp_clogfile("session_compact");
+        make_test_clogfile("pw", &path);
+        let today = Local::now().format("%d/%m/%Y").to_string();
+
+        let mut clog = Clog::open(&path, "pw").unwrap();
+        clog.add_file("entry", "kept").unwrap();
+        let before = clog.list().unwrap();
+
+        // every write leaves the metadata record it replaced behind
+        let dead = clog.dead_bytes().unwrap();
+        assert!(dead > 0);
+
+        // and a crashed write may leave ciphertext past the end of the metadata
+        let mut bytes = fs::read(&path).unwrap();
+        let clean_length = bytes.len() as u64;
+        bytes.extend_from_slice(&[0xAB; 300]);
+        fs::write(&path, bytes).unwrap();
+        assert_eq!(clog.dead_bytes().unwrap(), dead + 300);
+
+        assert_eq!(clog.compact().unwrap(), dead + 300);
+        assert_eq!(fs::metadata(&path).unwrap().len(), clean_length - dead);
+        assert_eq!(clog.compact().unwrap(), 0);
+
+        let reopened = Clog::open(&path, "pw").unwrap();
+        assert_eq!(reopened.list().unwrap(), before);
+        assert_eq!(reopened.read_file(&today, "entry").unwrap(), "kept");
+    }
+
+    #[test]
+    fn sessions_see_each_others_writes() {
+        let path = temp_clogfile("session_concurrent");
+        make_test_clogfile("pw", &path);
+        let today = Local::now().format("%d/%m/%Y").to_string();
+
+        let mut tui = Clog::open(&path, "pw").unwrap();
+        let mut sync = Clog::open(&path, "pw").unwrap();
+        tui.add_file("from tui", "one").unwrap();
+        sync.add_file("from sync", "two").unwrap();
+        tui.edit_file(&today, "from sync", "two, edited by tui")
+            .unwrap();
+
+        assert_eq!(sync.read_file(&today, "from tui").unwrap(), "one");
+
+        // what sync lists comes from the clogfile, not from its own last write
+        tui.add_file("from tui, later", "three").unwrap();
+        assert_eq!(sync.list().unwrap().len(), 4);
+        assert_eq!(
+            sync.ls(&today).unwrap().files,
+            ["Welcome", "from sync", "from tui", "from tui, later"]
+        );
+        assert_eq!(sync.list_versions(&today, "from sync").unwrap().len(), 1);
+        assert_eq!(
+            sync.list_versions(&today, "from sync").unwrap()[0].number,
+            2
+        );
+        tui.set_version_limit(3).unwrap();
+        assert_eq!(sync.version_limit().unwrap(), 3);
+
+        let reopened = Clog::open(&path, "pw").unwrap();
+        assert_eq!(reopened.list().unwrap().len(), 4);
+        assert_eq!(
+            reopened.read_file(&today, "from sync").unwrap(),
+            "two, edited by tui"
+        );
+    }
+
+    #[test]
+    fn held_lock_is_reported() {
+        let path = temp_clogfile("session_locked");
+        make_test_clogfile("pw", &path);
+        let mut clog = Clog::open_with_lock_policy(&path, "pw", LockPolicy::Try).unwrap();
+        let folder = clog.list().unwrap()[0].folder.clone();
+
+        let writer = lock_exclusive(&path, LockPolicy::Block).unwrap();
+        assert!(matches!(
+            clog.add_file("blocked", "nope"),
+            Err(ClogError::Locked)
+        ));
+        assert!(matches!(
+            clog.read_file(&folder, "Welcome"),
+            Err(ClogError::Locked)
+        ));
+        assert!(matches!(clog.list(), Err(ClogError::Locked)));
+        assert!(matches!(
+            Clog::open_with_lock_policy(&path, "pw", LockPolicy::Try),
+            Err(ClogError::Locked)
+        ));
+
+        drop(writer);
+        clog.add_file("unblocked", "yes").unwrap();
+        assert_eq!(clog.list().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn change_password_rewrites_the_slot_and_wipes_the_old_one() {
+        let path = temp_clogfile("session_password");
+        make_test_clogfile("old", &path);
+        let today = Local::now().format("%d/%m/%Y").to_string();
+
+        let mut clog = Clog::open(&path, "old").unwrap();
+        clog.add_file("entry", "survives").unwrap();
+        let before = clog.list().unwrap();
+
+        // a hard link keeps the old clogfile reachable after it is replaced
+        let old_copy = sibling_path(&path, "old");
+        fs::hard_link(&path, &old_copy).unwrap();
+        let old_bytes = fs::read(&old_copy).unwrap();
+
+        clog.change_password("new").unwrap();
+        clog.add_file("after", "still works").unwrap();
+
+        assert!(matches!(
+            Clog::open(&path, "old"),
+            Err(ClogError::WrongPassword)
+        ));
+        let reopened = Clog::open(&path, "new").unwrap();
+        assert_eq!(reopened.user(), "owner");
+        assert_eq!(reopened.list().unwrap().len(), before.len() + 1);
+        assert_eq!(reopened.read_file(&today, "entry").unwrap(), "survives");
+
+        // the old key slot is gone from the replaced file, header snapshots included
+        let wiped = fs::read(&old_copy).unwrap();
+        assert_ne!(wiped[..HEADER_LENGTH], old_bytes[..HEADER_LENGTH]);
+        assert!(find_headers(&old_bytes).len() > 1);
+        assert!(find_headers(&wiped).is_empty());
+        assert!(Clog::open(&old_copy, "old").is_err());
+
+        // nor does the new file keep a snapshot of it
+        let current = fs::read(&path).unwrap();
+        for offset in find_headers(&current) {
+            let header = parse_header_from_bytes(&current[offset..]).unwrap();
+            assert!(unlock_master_key(&header.key_slots, "old").is_err());
+        }
+    }
+
+    #[test]
+    fn users_have_their_own_passwords() {
+        let path = temp_clogfile("session_users");
+        make_test_clogfile("owner pw", &path);
+        let today = Local::now().format("%d/%m/%Y").to_string();
+
+        let mut owner = Clog::open(&path, "owner pw").unwrap();
+        owner.add_user("alice", "alice pw").unwrap();
+        assert!(matches!(
+            owner.add_user("alice", "again"),
+            Err(ClogError::UserExists { .. })
+        ));
+        assert_eq!(owner.users().unwrap(), ["owner", "alice"]);
+
+        // alice writes with her own password, the owner sees it
+        let mut alice = Clog::open(&path, "alice pw").unwrap();
+        assert_eq!(alice.user(), "alice");
+        alice.add_file("from alice", "hi").unwrap();
+        assert_eq!(owner.read_file(&today, "from alice").unwrap(), "hi");
+
+        alice.remove_user("owner").unwrap();
+        assert!(matches!(
+            Clog::open(&path, "owner pw"),
+            Err(ClogError::WrongPassword)
+        ));
+        assert!(matches!(
+            alice.remove_user("alice"),
+            Err(ClogError::InvalidUser(_))
+        ));
+        assert!(matches!(
+            alice.remove_user("bob"),
+            Err(ClogError::UserNotFound { .. })
+        ));
+        assert_eq!(alice.users().unwrap(), ["alice"]);
+    }
+
+    #[test]
+    fn change_password_survives_a_crash_at_every_step() {
+        let path = temp_clogfile("session_password_crash");
+
+        for step in 0.. {
+            let _ = fs::remove_file(&path);
+            make_test_clogfile("old", &path);
+            let mut clog = Clog::open(&path, "old").unwrap();
+
+            simulated_crash::arm(step);
+            let result = clog.change_password("new");
+            let crashed = simulated_crash::disarm().is_some();
+
+            // exactly one of the passwords opens the clogfile, with the entry intact
+            let reopened = Clog::open(&path, "new")
+                .or_else(|_| Clog::open(&path, "old"))
+                .unwrap();
+            let folder = reopened.list().unwrap()[0].folder.clone();
+            assert!(reopened.read_file(&folder, "Welcome").is_ok());
+            assert!(clog.read_file(&folder, "Welcome").is_ok());
+
+            if !crashed {
+                result.unwrap();
+                assert!(Clog::open(&path, "new").is_ok());
+                break;
+            }
+        }
+    }
+
+    /// adds an entry to a past folder and seals it
+    fn add_sealed_entry(clog: &mut Clog, foldername: &str, filename: &str, content: &str) {
+        clog.mutate(|metadata, base64_key, path| {
+            metadata
+                .folders
+                .insert(foldername.to_string(), Folder::new(FolderKind::Dated));
+            add_file_with_content(
+                metadata,
+                base64_key,
+                foldername,
+                filename,
+                content,
+                path,
+                &SystemClock,
+            )?;
+            metadata.get_folder_mut(foldername)?.make_read_only();
+            metadata.update_metadata_in_file(path, base64_key)
+        })
+        .unwrap();
+    }
+
+    #[test]
+    fn read_only_folders_need_an_audited_override() {
+        let path = temp_clogfile("session_read_only");
+        make_test_clogfile("pw", &path);
+        let mut clog = Clog::open(&path, "pw").unwrap();
+        add_sealed_entry(&mut clog, "01/01/2020", "old", "typo");
+
+        assert!(matches!(
+            clog.edit_file("01/01/2020", "old", "fixed"),
+            Err(ClogError::ReadOnlyFolder { .. })
+        ));
+        assert!(matches!(
+            clog.override_read_only("fix", |clog| clog.edit_file("01/01/2020", "nope", "x")),
+            Err(ClogError::NotFound { .. })
+        ));
+        assert!(clog.audit_log().unwrap().is_empty());
+
+        clog.override_read_only("fix typo reported by alice", |clog| {
+            clog.edit_file("01/01/2020", "old", "fixed")
+        })
+        .unwrap();
+
+        // the override ends with the closure
+        assert!(matches!(
+            clog.edit_file("01/01/2020", "old", "again"),
+            Err(ClogError::ReadOnlyFolder { .. })
+        ));
+
+        let reopened = Clog::open(&path, "pw").unwrap();
+        assert_eq!(reopened.read_file("01/01/2020", "old").unwrap(), "fixed");
+        let log = reopened.audit_log().unwrap();
+        assert_eq!(log.len(), 1);
+        assert_eq!(log[0].user, "owner");
+        assert_eq!(log[0].action, AuditAction::Edit);
+        assert_eq!(
+            (log[0].folder.as_str(), log[0].file.as_str()),
+            ("01/01/2020", "old")
+        );
+        assert_eq!(log[0].reason, "fix typo reported by alice");
+    }
+
+    #[test]
+    fn failed_mutation_leaves_session_unchanged() {
+        let path = temp_clogfile("session_failure");
+        make_test_clogfile("pw", &path);
+
+        let mut clog = Clog::open(&path, "pw").unwrap();
+        let before = clog.list().unwrap();
+        assert!(clog.edit_file("01/01/1970", "Welcome", "nope").is_err());
+        assert!(clog.add_file("Welcome", "duplicate").is_err());
+        assert_eq!(clog.list().unwrap(), before);
+    }
+
+    fn clock_at(rfc3339: &str) -> Arc<FixedClock> {
+        Arc::new(FixedClock::new(
+            chrono::DateTime::parse_from_rfc3339(rfc3339).unwrap(),
+        ))
+    }
+
+    #[test]
+    fn rollover_happens_at_midnight_and_is_persisted_once() {
+        let path = temp_clogfile("session_rollover");
+        let clock = clock_at("2025-05-25T23:59:30+02:00");
+        make_new_clogfile("pw", &path, TEST_KDF, &*clock).unwrap();
+
+        let mut clog = Clog::open(&path, "pw").unwrap().with_clock(clock.clone());
+        clog.add_file("late", "just before midnight").unwrap();
+        assert!(clog.roll_over().unwrap().is_empty());
+        let late = clog
+            .list()
+            .unwrap()
+            .into_iter()
+            .find(|entry| entry.name == "late");
+        assert_eq!(
+            late.unwrap().created_at,
+            "2025-05-25T23:59:30.000000000+02:00"
+        );
+
+        clock.advance(chrono::Duration::seconds(30));
+        let report = clog.roll_over().unwrap();
+        assert_eq!(report.sealed, ["25/05/2025"]);
+        assert_eq!(report.created.as_deref(), Some("26/05/2025"));
+
+        // a second rollover on the same day doesn't write anything
+        let nonce = parse_header_from_file(&path).unwrap().base64_nonce;
+        assert!(clog.roll_over().unwrap().is_empty());
+        assert_eq!(parse_header_from_file(&path).unwrap().base64_nonce, nonce);
+
+        // new entries go to the new day and the seal survives reopening
+        clog.add_file("early", "just after midnight").unwrap();
+        let mut clog = Clog::open(&path, "pw").unwrap().with_clock(clock);
+        assert_eq!(
+            clog.read_file("26/05/2025", "early").unwrap(),
+            "just after midnight"
+        );
+        assert!(matches!(
+            clog.edit_file("25/05/2025", "late", "changed"),
+            Err(ClogError::ReadOnlyFolder { .. })
+        ));
+    }
+
+    #[test]
+    fn day_is_decided_in_the_clock_timezone() {
+        let path = temp_clogfile("session_timezone");
+        let clock = clock_at("2025-05-25T22:30:00-04:00");
+        make_new_clogfile("pw", &path, TEST_KDF, &*clock).unwrap();
+        let mut clog = Clog::open(&path, "pw").unwrap().with_clock(clock.clone());
+        clog.add_file("new york", "evening").unwrap();
+
+        // the same instant seen from Tokyo is already the next day
+        clock.set(chrono::DateTime::parse_from_rfc3339("2025-05-26T11:30:00+09:00").unwrap());
+        let report = clog.roll_over().unwrap();
+        assert_eq!(report.sealed, ["25/05/2025"]);
+        assert_eq!(report.created.as_deref(), Some("26/05/2025"));
+        clog.add_file("tokyo", "morning").unwrap();
+        assert_eq!(clog.read_file("25/05/2025", "new york").unwrap(), "evening");
+        assert_eq!(clog.read_file("26/05/2025", "tokyo").unwrap(), "morning");
+
+        // going back west never seals a folder from the future
+        clock.set(chrono::DateTime::parse_from_rfc3339("2025-05-25T22:45:00-04:00").unwrap());
+        assert!(clog.roll_over().unwrap().is_empty());
+        assert!(
+            !clog
+                .metadata()
+                .get_folder("26/05/2025")
+                .unwrap()
+                .is_read_only()
+        );
+    }
+
+    #[test]
+    fn entries_of_the_same_instant_keep_their_order() {
+        let path = temp_clogfile("session_seq");
+        let clock = clock_at("2025-05-25T10:00:00+02:00");
+        make_new_clogfile("pw", &path, TEST_KDF, &*clock).unwrap();
+        let mut clog = Clog::open(&path, "pw").unwrap().with_clock(clock.clone());
+        clog.add_file("a", "first").unwrap();
+        clog.add_file("b", "second").unwrap();
+
+        clock.advance(chrono::Duration::milliseconds(1500));
+        clog.edit_file("25/05/2025", "a", "first, edited").unwrap();
+
+        let clog = Clog::open(&path, "pw").unwrap();
+        let entries = clog.list().unwrap();
+        let seqs: Vec<u64> = entries.iter().map(|entry| entry.seq).collect();
+        assert_eq!(seqs, [0, 1, 2]);
+        // Welcome, a and b were all created at the same instant
+        assert_eq!(entries[0].created_at, entries[1].created_at);
+        assert_eq!(entries[1].created_at, entries[2].created_at);
+        assert_eq!(
+            entries[1].modified_at,
+            "2025-05-25T10:00:01.500000000+02:00"
+        );
+        assert_eq!(entries[2].modified_at, entries[2].created_at);
+        assert_eq!(clog.read_file("25/05/2025", "b").unwrap(), "second");
+    }
+
+    #[test]
+    fn delete_leaves_dead_bytes_until_compacted() {
+        let path = temp_clogfile("session_delete");
+        let clock = clock_at("2025-05-25T10:00:00+02:00");
+        make_new_clogfile("pw", &path, TEST_KDF, &*clock).unwrap();
+        let mut clog = Clog::open(&path, "pw").unwrap().with_clock(clock.clone());
+        clog.add_file("a", "first day").unwrap();
+        clock.advance(chrono::Duration::days(1));
+        clog.add_file("b", "second day").unwrap();
+        clog.compact().unwrap();
+
+        let length = clog.metadata().get_file("25/05/2025", "a").unwrap().length;
+        let b_offset = clog.metadata().get_file("26/05/2025", "b").unwrap().offset;
+        let metadata_length = parse_header_from_file(&path).unwrap().metadata_length;
+        clog.override_read_only("yesterday", |clog| {
+            clog.delete_file("25/05/2025", "a", false)
+        })
+        .unwrap();
+
+        // nothing moves, the entry and the old metadata record with its header snapshot
+        // are dead bytes now
+        assert_eq!(
+            clog.metadata().get_file("26/05/2025", "b").unwrap().offset,
+            b_offset
+        );
+        let dead = (length + HEADER_LENGTH + metadata_length) as u64;
+        assert_eq!(clog.dead_bytes().unwrap(), dead);
+        assert_eq!(clog.compact().unwrap(), dead);
+        let header = parse_header_from_file(&path).unwrap();
+        assert_eq!(
+            fs::metadata(&path).unwrap().len() as usize,
+            header.metadata_offset + header.metadata_length
+        );
+
+        let mut clog = Clog::open(&path, "pw").unwrap();
+        assert_eq!(clog.list().unwrap().len(), 2);
+        assert_eq!(clog.read_file("26/05/2025", "b").unwrap(), "second day");
+        assert!(matches!(
+            clog.read_file("25/05/2025", "a"),
+            Err(ClogError::NotFound { .. })
+        ));
+        assert!(matches!(
+            clog.delete_file("25/05/2025", "a", false),
+            Err(ClogError::NotFound { .. })
+        ));
+    }
+
+    /// every metadata record in `bytes` a header snapshot points at that still decrypts
+    fn readable_metadata_records(bytes: &[u8], base64_key: &str) -> Vec<Metadata> {
+        find_headers(bytes)
+            .into_iter()
+            .filter_map(|offset| {
+                let header = parse_header_from_bytes(&bytes[offset..]).ok()?;
+                let record = bytes
+                    .get(header.metadata_offset..header.metadata_offset + header.metadata_length)?;
+                Metadata::parse_encrypted_metadata(record, base64_key, &header.base64_nonce).ok()
+            })
+            .collect()
+    }
+
+    fn contains(bytes: &[u8], needle: &[u8]) -> bool {
+        bytes.windows(needle.len()).any(|window| window == needle)
+    }
+
+    #[test]
+    fn wipe_leaves_no_ciphertext_or_metadata_of_the_entry_behind() {
+        for wipe in [false, true] {
+            let path = temp_clogfile("session_wipe");
+            make_test_clogfile("pw", &path);
+            let mut clog = Clog::open(&path, "pw").unwrap();
+            clog.add_file("keep", "stays readable").unwrap();
+            clog.add_file("secret", "first draft").unwrap();
+            let folder = clog.list().unwrap()[0].folder.clone();
+            let ciphertext_of = |clog: &Clog| {
+                let (_, _, offset, length) = clog
+                    .metadata()
+                    .get_file(&folder, "secret")
+                    .unwrap()
+                    .get_file_parameters();
+                fs::read(&path).unwrap()[offset..offset + length].to_vec()
+            };
+
+            // without a version history the first draft is only dead bytes after the edit
+            let first_draft = ciphertext_of(&clog);
+            clog.edit_file(&folder, "secret", "burn after reading")
+                .unwrap();
+            let current = ciphertext_of(&clog);
+
+            // a second link keeps the clogfile a wiping delete replaces around
+            let old_path = sibling_path(&path, "old");
+            let _ = fs::remove_file(&old_path);
+            fs::hard_link(&path, &old_path).unwrap();
+
+            clog.delete_file(&folder, "secret", wipe).unwrap();
+            for bytes in [fs::read(&path).unwrap(), fs::read(&old_path).unwrap()] {
+                assert_eq!(contains(&bytes, &first_draft), !wipe);
+                assert_eq!(contains(&bytes, &current), !wipe);
+                let records = readable_metadata_records(&bytes, &clog.base64_key);
+                let naming_it = records
+                    .iter()
+                    .filter(|metadata| metadata.get_file(&folder, "secret").is_ok())
+                    .count();
+                assert_eq!(naming_it > 0, !wipe);
+            }
+
+            assert_eq!(clog.dead_bytes().unwrap() == 0, wipe);
+            let clog = Clog::open(&path, "pw").unwrap();
+            assert_eq!(clog.read_file(&folder, "keep").unwrap(), "stays readable");
+            assert!(clog.metadata().get_file(&folder, "secret").is_err());
+            fs::remove_file(&old_path).unwrap();
+        }
+    }
+
+    #[test]
+    fn read_only_entries_are_only_deleted_with_an_override() {
+        let path = temp_clogfile("session_delete_read_only");
+        make_test_clogfile("pw", &path);
+        let mut clog = Clog::open(&path, "pw").unwrap();
+        add_sealed_entry(&mut clog, "01/01/2020", "old", "regret");
+
+        assert!(matches!(
+            clog.delete_file("01/01/2020", "old", true),
+            Err(ClogError::ReadOnlyFolder { .. })
+        ));
+        clog.override_read_only("asked to forget", |clog| {
+            clog.delete_file("01/01/2020", "old", true)
+        })
+        .unwrap();
+
+        let clog = Clog::open(&path, "pw").unwrap();
+        assert!(clog.metadata().get_file("01/01/2020", "old").is_err());
+        let log = clog.audit_log().unwrap();
+        assert_eq!(log.len(), 1);
+        assert_eq!(log[0].action, AuditAction::Delete);
+        assert_eq!(log[0].reason, "asked to forget");
+    }
+
+    #[test]
+    fn rename_and_move_only_rewrite_the_metadata() {
+        let path = temp_clogfile("session_rename");
+        let clock = clock_at("2025-05-25T10:00:00+02:00");
+        make_new_clogfile("pw", &path, TEST_KDF, &*clock).unwrap();
+        let mut clog = Clog::open(&path, "pw").unwrap().with_clock(clock.clone());
+        clog.add_file("tpyo", "mistyped").unwrap();
+        clog.add_file("b", "taken").unwrap();
+        clog.mkdir("26/05/2025").unwrap();
+        clog.add_file_to("26/05/2025", "b", "taken tomorrow too")
+            .unwrap();
+
+        // only metadata records are appended after the entries
+        let metadata_offset = parse_header_from_file(&path).unwrap().metadata_offset;
+        let entries =
+            |path: &PathBuf| fs::read(path).unwrap()[HEADER_LENGTH..metadata_offset].to_vec();
+        let before = entries(&path);
+
+        for bad_name in ["", "..", "typo/fixed"] {
+            assert!(matches!(
+                clog.rename_file("25/05/2025", "tpyo", bad_name),
+                Err(ClogError::InvalidPath(_))
+            ));
+        }
+        clog.rename_file("25/05/2025", "tpyo", "typo").unwrap();
+        assert!(matches!(
+            clog.rename_file("25/05/2025", "typo", "b"),
+            Err(ClogError::AlreadyExists { .. })
+        ));
+        clog.move_file("25/05/2025", "26/05/2025", "typo").unwrap();
+        assert!(matches!(
+            clog.move_file("25/05/2025", "26/05/2025", "b"),
+            Err(ClogError::AlreadyExists { .. })
+        ));
+        assert!(matches!(
+            clog.move_file("26/05/2025", "01/01/2020", "typo"),
+            Err(ClogError::FolderNotFound { .. })
+        ));
+        assert_eq!(entries(&path), before);
+
+        let clog = Clog::open(&path, "pw").unwrap();
+        assert_eq!(clog.read_file("26/05/2025", "typo").unwrap(), "mistyped");
+        assert_eq!(clog.read_file("25/05/2025", "b").unwrap(), "taken");
+        assert!(matches!(
+            clog.read_file("25/05/2025", "tpyo"),
+            Err(ClogError::NotFound { .. })
+        ));
+    }
+
+    #[test]
+    fn rename_and_move_respect_read_only_folders() {
+        let path = temp_clogfile("session_rename_read_only");
+        make_test_clogfile("pw", &path);
+        let mut clog = Clog::open(&path, "pw").unwrap();
+        let today = clog.list().unwrap()[0].folder.clone();
+        add_sealed_entry(&mut clog, "01/01/2020", "old", "sealed");
+
+        assert!(matches!(
+            clog.rename_file("01/01/2020", "old", "older"),
+            Err(ClogError::ReadOnlyFolder { .. })
+        ));
+        assert!(matches!(
+            clog.move_file(&today, "01/01/2020", "Welcome"),
+            Err(ClogError::ReadOnlyFolder { .. })
+        ));
+
+        clog.override_read_only("tidy up", |clog| {
+            clog.rename_file("01/01/2020", "old", "older")?;
+            clog.move_file("01/01/2020", &today, "older")
+        })
+        .unwrap();
+        assert_eq!(clog.read_file(&today, "older").unwrap(), "sealed");
+        let actions: Vec<AuditAction> =
+            clog.audit_log().unwrap().iter().map(|r| r.action).collect();
+        assert_eq!(actions, [AuditAction::Rename, AuditAction::Move]);
+    }
+
+    #[test]
+    fn nested_folders_hold_entries_anywhere() {
+        let path = temp_clogfile("session_nested");
+        let clock = clock_at("2025-05-25T10:00:00+02:00");
+        make_new_clogfile("pw", &path, TEST_KDF, &*clock).unwrap();
+        let mut clog = Clog::open(&path, "pw").unwrap().with_clock(clock);
+
+        for folder in [
+            "projects",
+            "projects/clog",
+            "projects/clog/ideas",
+            "25/05/2025/notes",
+        ] {
+            clog.mkdir(folder).unwrap();
+        }
+        assert!(matches!(
+            clog.mkdir("projects/clog"),
+            Err(ClogError::FolderExists { .. })
+        ));
+        assert!(matches!(
+            clog.mkdir("nowhere/else"),
+            Err(ClogError::FolderNotFound { .. })
+        ));
+
+        clog.add_file_to("projects/clog/ideas", "tui", "rename entries")
+            .unwrap();
+        clog.add_file_to("25/05/2025/notes", "call", "call back")
+            .unwrap();
+        // compaction packs nested entries along with everything else
+        clog.edit_file("25/05/2025", "Welcome", "a much longer welcome than before")
+            .unwrap();
+
+        assert_eq!(clog.ls("").unwrap().folders, ["25/05/2025", "projects"]);
+        let listing = clog.ls("25/05/2025").unwrap();
+        assert_eq!(listing.folders, ["notes"]);
+        assert_eq!(listing.files, ["Welcome"]);
+        assert_eq!(clog.ls("projects/clog").unwrap().folders, ["ideas"]);
+
+        clog.move_file("25/05/2025/notes", "projects/clog/ideas", "call")
+            .unwrap();
+        assert!(matches!(
+            clog.rmdir("projects/clog"),
+            Err(ClogError::FolderNotEmpty { .. })
+        ));
+        clog.rmdir("25/05/2025/notes").unwrap();
+        // the packed metadata may be a few bytes shorter, its offsets got smaller
+        let dead = clog.dead_bytes().unwrap();
+        let metadata_length = parse_header_from_file(&path).unwrap().metadata_length;
+        let freed = clog.compact().unwrap();
+        let packed_length = parse_header_from_file(&path).unwrap().metadata_length;
+        assert_eq!(freed, dead + metadata_length as u64 - packed_length as u64);
+        assert_eq!(clog.dead_bytes().unwrap(), 0);
+
+        let clog = Clog::open(&path, "pw").unwrap();
+        let folders: Vec<String> = clog
+            .list()
+            .unwrap()
+            .into_iter()
+            .map(|entry| entry.folder)
+            .collect();
+        assert_eq!(
+            folders,
+            ["25/05/2025", "projects/clog/ideas", "projects/clog/ideas"]
+        );
+        assert_eq!(
+            clog.read_file("projects/clog/ideas", "tui").unwrap(),
+            "rename entries"
+        );
+        assert_eq!(
+            clog.read_file("projects/clog/ideas", "call").unwrap(),
+            "call back"
+        );
+        assert!(clog.ls("25/05/2025").unwrap().folders.is_empty());
+    }
+
+    #[test]
+    fn folders_inside_a_sealed_day_are_read_only() {
+        let path = temp_clogfile("session_nested_read_only");
+        let clock = clock_at("2025-05-25T10:00:00+02:00");
+        make_new_clogfile("pw", &path, TEST_KDF, &*clock).unwrap();
+        let mut clog = Clog::open(&path, "pw").unwrap().with_clock(clock.clone());
+        clog.mkdir("25/05/2025/notes").unwrap();
+        clog.add_file_to("25/05/2025/notes", "todo", "buy milk")
+            .unwrap();
+        clog.mkdir("projects").unwrap();
+
+        clock.advance(chrono::Duration::days(1));
+        assert_eq!(clog.roll_over().unwrap().sealed, ["25/05/2025"]);
+
+        let read_only =
+            |result: Result<()>| matches!(result, Err(ClogError::ReadOnlyFolder { .. }));
+        assert!(read_only(clog.edit_file("25/05/2025/notes", "todo", "x")));
+        assert!(read_only(clog.add_file_to("25/05/2025/notes", "more", "x")));
+        assert!(read_only(clog.mkdir("25/05/2025/notes/more")));
+        assert!(read_only(clog.rmdir("25/05/2025/notes")));
+
+        // folders that aren't days are never sealed
+        clog.add_file_to("projects", "plan", "still writable")
+            .unwrap();
+        clog.override_read_only("late addition", |clog| {
+            clog.add_file_to("25/05/2025/notes", "more", "forgot this")
+        })
+        .unwrap();
+        assert_eq!(clog.audit_log().unwrap()[0].action, AuditAction::Add);
+    }
+
+    #[test]
+    fn named_folders_stay_writable_until_sealed() {
+        let path = temp_clogfile("session_named");
+        let clock = clock_at("2025-05-25T10:00:00+02:00");
+        make_new_clogfile("pw", &path, TEST_KDF, &*clock).unwrap();
+        let mut clog = Clog::open(&path, "pw").unwrap().with_clock(clock.clone());
+        clog.mkdir("Recipes").unwrap();
+        clog.add_file_to("Recipes", "pancakes", "eggs, flour, milk")
+            .unwrap();
+        assert_eq!(clog.folder_kind("Recipes").unwrap(), FolderKind::Named);
+        assert_eq!(clog.folder_kind("25/05/2025").unwrap(), FolderKind::Dated);
+
+        // a day folder is done with once its day is over, even before the rollover
+        clock.advance(chrono::Duration::days(1));
+        assert!(matches!(
+            clog.edit_file("25/05/2025", "Welcome", "too late"),
+            Err(ClogError::ReadOnlyFolder { .. })
+        ));
+        clog.edit_file("Recipes", "pancakes", "eggs, flour, milk, sugar")
+            .unwrap();
+
+        // and only day folders are sealed by it
+        assert_eq!(clog.roll_over().unwrap().sealed, ["25/05/2025"]);
+        clock.advance(chrono::Duration::days(30));
+        clog.roll_over().unwrap();
+        clog.add_file_to("Recipes", "waffles", "like pancakes")
+            .unwrap();
+
+        clog.seal_folder("Recipes").unwrap();
+        assert!(matches!(
+            clog.add_file_to("Recipes", "crepes", "thinner"),
+            Err(ClogError::ReadOnlyFolder { .. })
+        ));
+
+        let clog = Clog::open(&path, "pw").unwrap();
+        assert_eq!(clog.folder_kind("Recipes").unwrap(), FolderKind::Named);
+        assert!(
+            clog.metadata()
+                .get_folder("Recipes")
+                .unwrap()
+                .is_read_only()
+        );
+    }
+
+    #[test]
+    fn sealed_today_takes_no_new_entries_without_an_override() {
+        let path = temp_clogfile("session_sealed_today");
+        let clock = clock_at("2025-05-25T10:00:00+02:00");
+        make_new_clogfile("pw", &path, TEST_KDF, &*clock).unwrap();
+        let mut clog = Clog::open(&path, "pw").unwrap().with_clock(clock);
+        clog.seal_folder("25/05/2025").unwrap();
+
+        assert!(matches!(
+            clog.add_file("late", "sealed already"),
+            Err(ClogError::ReadOnlyFolder { .. })
+        ));
+        assert!(matches!(
+            clog.add_file_to("25/05/2025", "late", "sealed already"),
+            Err(ClogError::ReadOnlyFolder { .. })
+        ));
+        assert!(clog.audit_log().unwrap().is_empty());
+
+        clog.override_read_only("forgot one", |clog| clog.add_file("late", "added anyway"))
+            .unwrap();
+        let record = &clog.audit_log().unwrap()[0];
+        assert_eq!(
+            (record.action, record.folder.as_str(), record.file.as_str()),
+            (AuditAction::Add, "25/05/2025", "late")
+        );
+        assert_eq!(record.at, "2025-05-25T10:00:00.000000000+02:00");
+        assert_eq!(
+            clog.read_file("25/05/2025", "late").unwrap(),
+            "added anyway"
+        );
+    }
+
+    #[test]
+    fn version_history_keeps_earlier_contents_up_to_the_limit() {
+        let path = temp_clogfile("session_versions");
+        let clock = clock_at("2025-05-25T10:00:00+02:00");
+        make_new_clogfile("pw", &path, TEST_KDF, &*clock).unwrap();
+        let mut clog = Clog::open(&path, "pw").unwrap().with_clock(clock.clone());
+        let before = parse_header_from_file(&path).unwrap().metadata_offset;
+
+        clog.set_version_limit(2).unwrap();
+        clog.add_file("draft", "one").unwrap();
+        for content in ["two", "three", "four"] {
+            clock.advance(chrono::Duration::minutes(1));
+            clog.edit_file("25/05/2025", "draft", content).unwrap();
+        }
+        clog.add_file("later", "stored after every version")
+            .unwrap();
+
+        let versions = clog.list_versions("25/05/2025", "draft").unwrap();
+        let numbers: Vec<u64> = versions.iter().map(|version| version.number).collect();
+        assert_eq!(numbers, [2, 3, 4]);
+        assert!(versions[2].current && !versions[1].current);
+        assert!(versions[0].modified_at < versions[1].modified_at);
+        assert!(matches!(
+            clog.get_version("25/05/2025", "draft", 1),
+            Err(ClogError::VersionNotFound { version: 1, .. })
+        ));
+        assert_eq!(clog.get_version("25/05/2025", "draft", 2).unwrap(), "two");
+
+        // restoring is an edit too, so the bad edit stays in the history
+        clog.restore_version("25/05/2025", "draft", 2).unwrap();
+        assert_eq!(clog.read_file("25/05/2025", "draft").unwrap(), "two");
+        assert_eq!(clog.get_version("25/05/2025", "draft", 4).unwrap(), "four");
+
+        clog.compact().unwrap();
+        let mut clog = Clog::open(&path, "pw").unwrap().with_clock(clock.clone());
+        assert_eq!(clog.version_limit().unwrap(), 2);
+        assert_eq!(clog.get_version("25/05/2025", "draft", 3).unwrap(), "three");
+        assert_eq!(
+            clog.read_file("25/05/2025", "later").unwrap(),
+            "stored after every version"
+        );
+
+        // turning the history off drops the versions on the next edit
+        clog.set_version_limit(0).unwrap();
+        clog.edit_file("25/05/2025", "draft", "final").unwrap();
+        let versions = clog.list_versions("25/05/2025", "draft").unwrap();
+        assert_eq!(versions.len(), 1);
+        assert_eq!(versions[0].number, 6);
+
+        clock.advance(chrono::Duration::days(1));
+        assert!(matches!(
+            clog.restore_version("25/05/2025", "draft", 6),
+            Err(ClogError::ReadOnlyFolder { .. })
+        ));
+
+        // deleting removes every version along with the entry
+        clog.set_version_limit(3).unwrap();
+        clog.override_read_only("cleanup", |clog| {
+            clog.edit_file("25/05/2025", "later", "again")?;
+            clog.delete_file("25/05/2025", "later", true)?;
+            clog.delete_file("25/05/2025", "draft", true)
+        })
+        .unwrap();
+        clog.compact().unwrap();
+        assert_eq!(
+            parse_header_from_file(&path).unwrap().metadata_offset,
+            before
+        );
+    }
+
+    #[test]
+    fn appends_leave_everything_stored_after_the_entry_in_place() {
+        let path = temp_clogfile("session_append");
+        let clock = clock_at("2025-05-25T10:00:00+02:00");
+        make_new_clogfile("pw", &path, TEST_KDF, &*clock).unwrap();
+        let mut clog = Clog::open(&path, "pw").unwrap().with_clock(clock.clone());
+        let before = parse_header_from_file(&path).unwrap().metadata_offset;
+        clog.add_file("log", "woke up").unwrap();
+        clog.add_file("later", "stored after the log").unwrap();
+
+        let later = clog
+            .metadata()
+            .get_file("25/05/2025", "later")
+            .unwrap()
+            .offset;
+        clog.append_to_file("25/05/2025", "log", "\ncoffee")
+            .unwrap();
+        clog.append_to_file("25/05/2025", "log", "\nwent out")
+            .unwrap();
+        assert_eq!(
+            clog.metadata()
+                .get_file("25/05/2025", "later")
+                .unwrap()
+                .offset,
+            later
+        );
+        assert_eq!(
+            clog.read_file("25/05/2025", "log").unwrap(),
+            "woke up\ncoffee\nwent out"
+        );
+
+        // the chunks of an edited content stay with its version
+        clog.set_version_limit(1).unwrap();
+        clog.edit_file("25/05/2025", "log", "slept in").unwrap();
+        clog.append_to_file("25/05/2025", "log", "\nlunch").unwrap();
+        assert_eq!(
+            clog.get_version("25/05/2025", "log", 1).unwrap(),
+            "woke up\ncoffee\nwent out"
+        );
+
+        clog.compact().unwrap();
+        let mut clog = Clog::open(&path, "pw").unwrap().with_clock(clock.clone());
+        assert_eq!(
+            clog.read_file("25/05/2025", "log").unwrap(),
+            "slept in\nlunch"
+        );
+        assert_eq!(
+            clog.get_version("25/05/2025", "log", 1).unwrap(),
+            "woke up\ncoffee\nwent out"
+        );
+
+        // without history an edit drops the chunks it replaces
+        clog.set_version_limit(0).unwrap();
+        clog.edit_file("25/05/2025", "log", "rewritten").unwrap();
+        assert!(
+            clog.metadata()
+                .get_file("25/05/2025", "log")
+                .unwrap()
+                .chunks
+                .is_empty()
+        );
+        assert_eq!(clog.read_file("25/05/2025", "log").unwrap(), "rewritten");
+        assert_eq!(
+            clog.read_file("25/05/2025", "later").unwrap(),
+            "stored after the log"
+        );
+
+        clock.advance(chrono::Duration::days(1));
+        assert!(matches!(
+            clog.append_to_file("25/05/2025", "log", "too late"),
+            Err(ClogError::ReadOnlyFolder { .. })
+        ));
+        clog.override_read_only("cleanup", |clog| {
+            clog.delete_file("25/05/2025", "log", false)?;
+            clog.delete_file("25/05/2025", "later", false)
+        })
+        .unwrap();
+        clog.compact().unwrap();
+        assert_eq!(
+            parse_header_from_file(&path).unwrap().metadata_offset,
+            before
+        );
+    }
+
+    #[test]
+    fn writes_leave_everything_before_them_untouched() {
+        let path = temp_clogfile("session_append_only");
+        make_test_clogfile("pw", &path);
+        let mut clog = Clog::open(&path, "pw").unwrap();
+        let folder = clog.list().unwrap()[0].folder.clone();
+        clog.add_file("big", &"x".repeat(64 * 1024)).unwrap();
+
+        let before = fs::read(&path).unwrap();
+        clog.edit_file(&folder, "Welcome", "stored before the big entry")
+            .unwrap();
+        clog.append_to_file(&folder, "big", "and one more line")
+            .unwrap();
+        clog.delete_file(&folder, "Welcome", false).unwrap();
+
+        // only the metadata pointer in the header changed, the rest was appended:
+        // three metadata records with their header snapshots, not the big entry
+        let after = fs::read(&path).unwrap();
+        assert_eq!(
+            after[METADATA_POINTER.end..before.len()],
+            before[METADATA_POINTER.end..]
+        );
+        assert!(after.len() - before.len() < 8192);
+        assert_eq!(
+            clog.read_file(&folder, "big").unwrap(),
+            "x".repeat(64 * 1024) + "and one more line"
+        );
+    }
+}
@@ -0,0 +1,165 @@
+use crate::backend::{
+    clock::SystemClock, file_operations::utils::make_new_clogfile, user::utils::derive_key::Kdf,
+};
+use std::{
+    path::PathBuf,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+static COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+/// gives a fresh, not yet existing clogfile path inside the system temp directory
+pub fn temp_clogfile(name: &str) -> PathBuf {
+    let id = COUNTER.fetch_add(1, Ordering::SeqCst);
+    let path = std::env::temp_dir().join(format!(
+        "clog_test_{}_{}_{}.clog",
+        std::process::id(),
+        id,
+        name
+    ));
+    let _ = std::fs::remove_file(&path);
+    path
+}
+
+/// tests derive a lot of keys, keep them cheap
+pub const TEST_KDF: Kdf = Kdf::Argon2id {
+    memory_kib: 64,
+    iterations: 1,
+    parallelism: 1,
+};
+
+/// creates a clogfile like `add_new_user`, but with [`TEST_KDF`] for the key slot
+pub fn make_test_clogfile(password: &str, clogfile_path: &PathBuf) {
+    make_new_clogfile(password, clogfile_path, TEST_KDF, &SystemClock).unwrap();
+}
+
+/// time of day every entry of the legacy clogfiles below was created at
+pub const LEGACY_CREATED_AT: &str = "09:30:00 PM";
+
+/// serializes `metadata` the way clogfiles before v5 stored it, entries only
+/// keeping their local time of day
+fn legacy_metadata_json(metadata: &crate::backend::metadata::metadata::Metadata) -> String {
+    let mut json = serde_json::to_value(metadata).unwrap();
+    json.as_object_mut().unwrap().remove("next_seq");
+    for folder in json["folders"].as_object_mut().unwrap().values_mut() {
+        for file in folder["files"].as_object_mut().unwrap().values_mut() {
+            let file = file.as_object_mut().unwrap();
+            file.remove("seq");
+            file.remove("modified_at");
+            file.insert("created_at".to_string(), LEGACY_CREATED_AT.into());
+        }
+    }
+    json.to_string()
+}
+
+/// writes a clogfile in the legacy v1 layout (ASCII header, base64 blobs)
+/// holding a single "Welcome" entry in today's folder
+pub fn make_v1_clogfile(password: &str, clogfile_path: &PathBuf) {
+    use crate::backend::{
+        clock::SystemClock,
+        file_operations::content::encrypt_data,
+        metadata::init::init,
+        user::utils::{
+            derive_key::LEGACY_KDF,
+            generate_keys::{generate_base64_nonce, generate_base64_salt},
+        },
+    };
+    use base64::{Engine, engine::general_purpose};
+    use chrono::Local;
+
+    const V1_HEADER_LENGTH: usize = 72;
+    let base64_salt = generate_base64_salt();
+    let base64_nonce = generate_base64_nonce();
+    let base64_key = LEGACY_KDF
+        .derive_key_base64(password, &base64_salt)
+        .unwrap();
+    let foldername = Local::now().format("%d/%m/%Y").to_string();
+
+    // the entry goes right after the header
+    let mut metadata = init(&SystemClock);
+    metadata
+        .add_file("Welcome", &foldername, V1_HEADER_LENGTH, 0, &SystemClock)
+        .unwrap();
+    let (file_key, file_nonce, _, _) = metadata
+        .get_file(&foldername, "Welcome")
+        .unwrap()
+        .get_file_parameters();
+    let content = general_purpose::STANDARD
+        .encode(encrypt_data(b"legacy welcome", file_key, file_nonce).unwrap());
+    metadata
+        .get_file_mut(&foldername, "Welcome")
+        .unwrap()
+        .update_length(content.len());
+
+    // followed by the metadata
+    let serialized = legacy_metadata_json(&metadata);
+    let encrypted_metadata = general_purpose::STANDARD
+        .encode(encrypt_data(serialized.as_bytes(), &base64_key, &base64_nonce).unwrap());
+
+    let header = format!(
+        "clog @1.0.1\n{}.{}.{:08}.{:08}\n",
+        base64_salt,
+        base64_nonce,
+        encrypted_metadata.len(),
+        V1_HEADER_LENGTH + content.len()
+    );
+    assert_eq!(header.len(), V1_HEADER_LENGTH);
+    std::fs::write(clogfile_path, header + &content + &encrypted_metadata).unwrap();
+}
+
+/// writes a clogfile in the v2 layout (binary header with a single salt, raw
+/// ciphertext) holding a single "Welcome" entry in today's folder
+pub fn make_v2_clogfile(password: &str, clogfile_path: &PathBuf) {
+    use crate::backend::{
+        clock::SystemClock,
+        file_operations::content::encrypt_data,
+        header::utils::MAGIC,
+        metadata::init::init,
+        user::utils::{derive_key::LEGACY_KDF, generate_keys::generate_base64_salt},
+    };
+    use base64::{Engine, engine::general_purpose};
+    use chrono::Local;
+
+    const V2_HEADER_LENGTH: usize = 54;
+    let base64_salt = generate_base64_salt();
+    let base64_key = LEGACY_KDF
+        .derive_key_base64(password, &base64_salt)
+        .unwrap();
+    let foldername = Local::now().format("%d/%m/%Y").to_string();
+    let nonce = [3u8; 12];
+
+    // the entry goes right after the header
+    let mut metadata = init(&SystemClock);
+    metadata
+        .add_file("Welcome", &foldername, V2_HEADER_LENGTH, 0, &SystemClock)
+        .unwrap();
+    let (file_key, file_nonce, _, _) = metadata
+        .get_file(&foldername, "Welcome")
+        .unwrap()
+        .get_file_parameters();
+    let content = encrypt_data(b"v2 welcome", file_key, file_nonce).unwrap();
+    metadata
+        .get_file_mut(&foldername, "Welcome")
+        .unwrap()
+        .update_length(content.len());
+
+    // followed by the metadata
+    let serialized = legacy_metadata_json(&metadata);
+    let encrypted_metadata = encrypt_data(
+        serialized.as_bytes(),
+        &base64_key,
+        &general_purpose::STANDARD.encode(nonce),
+    )
+    .unwrap();
+
+    let mut bytes = MAGIC.to_vec();
+    bytes.extend_from_slice(&2u16.to_le_bytes());
+    bytes.extend_from_slice(&general_purpose::STANDARD.decode(&base64_salt).unwrap());
+    bytes.extend_from_slice(&nonce);
+    bytes.extend_from_slice(&((V2_HEADER_LENGTH + content.len()) as u64).to_le_bytes());
+    bytes.extend_from_slice(&(encrypted_metadata.len() as u64).to_le_bytes());
+    assert_eq!(bytes.len(), V2_HEADER_LENGTH);
+    bytes.extend_from_slice(&content);
+    bytes.extend_from_slice(&encrypted_metadata);
+    std::fs::write(clogfile_path, bytes).unwrap();
+}
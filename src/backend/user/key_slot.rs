@@ -0,0 +1,141 @@
+use super::utils::{
+    derive_key::{Kdf, LEGACY_KDF},
+    generate_keys::{generate_base64_nonce, generate_base64_salt},
+};
+use crate::backend::{
+    config::{DEFAULT_USER, MAX_USER_NAME_LENGTH},
+    error::{ClogError, Result},
+    file_operations::content::{decrypt_bytes, encrypt_data},
+};
+use base64::{Engine, engine::general_purpose};
+
+/// One user's way into the clogfile: the master key, wrapped with a key
+/// derived from that user's password.
+#[derive(Clone, Debug)]
+pub struct KeySlot {
+    pub user: String,
+    /// how the password key is derived from the password and salt
+    pub kdf: Kdf,
+    pub base64_salt: String,
+    pub base64_nonce: String,
+    /// encrypted master key, empty for v1/v2 clogfiles where the
+    /// password derived key was used directly
+    pub wrapped_key: Vec<u8>,
+}
+
+impl KeySlot {
+    /// wraps `base64_master_key` for `user` under a fresh salt
+    pub fn new(user: &str, password: &str, kdf: Kdf, base64_master_key: &str) -> Result<Self> {
+        if user.is_empty() || user.len() > MAX_USER_NAME_LENGTH {
+            return Err(ClogError::InvalidUser(format!(
+                "user names must be 1 to {} bytes long",
+                MAX_USER_NAME_LENGTH
+            )));
+        }
+
+        let base64_salt = generate_base64_salt();
+        let password_key = kdf.derive_key_base64(password, &base64_salt)?;
+        Self::with_password_key(user, kdf, &base64_salt, &password_key, base64_master_key)
+    }
+
+    /// wraps `base64_master_key` with a key already derived by `kdf` from the password
+    /// and `base64_salt`
+    pub fn with_password_key(
+        user: &str,
+        kdf: Kdf,
+        base64_salt: &str,
+        base64_password_key: &str,
+        base64_master_key: &str,
+    ) -> Result<Self> {
+        let base64_nonce = generate_base64_nonce();
+        let master_key = general_purpose::STANDARD
+            .decode(base64_master_key)
+            .map_err(|_| ClogError::CorruptMetadata("invalid master key".to_string()))?;
+
+        Ok(KeySlot {
+            user: user.to_string(),
+            kdf,
+            base64_salt: base64_salt.to_string(),
+            wrapped_key: encrypt_data(&master_key, base64_password_key, &base64_nonce)?,
+            base64_nonce,
+        })
+    }
+
+    /// slot of a v1/v2 clogfile, which only had a salt
+    pub fn legacy(base64_salt: &str) -> Self {
+        KeySlot {
+            user: DEFAULT_USER.to_string(),
+            kdf: LEGACY_KDF,
+            base64_salt: base64_salt.to_string(),
+            base64_nonce: String::new(),
+            wrapped_key: Vec::new(),
+        }
+    }
+
+    pub fn is_legacy(&self) -> bool {
+        self.wrapped_key.is_empty()
+    }
+
+    /// gives the master key if `password` belongs to this slot
+    ///
+    /// legacy slots can't tell, their key only fails once the metadata is decrypted
+    pub fn unlock(&self, password: &str) -> Result<String> {
+        let password_key = self.kdf.derive_key_base64(password, &self.base64_salt)?;
+        if self.is_legacy() {
+            return Ok(password_key);
+        }
+
+        let master_key = decrypt_bytes(&self.wrapped_key, &password_key, &self.base64_nonce)?;
+        Ok(general_purpose::STANDARD.encode(master_key))
+    }
+}
+
+/// tries every slot in turn and gives the user and master key of the first one
+/// `password` unlocks
+pub fn unlock_master_key(key_slots: &[KeySlot], password: &str) -> Result<(String, String)> {
+    for slot in key_slots {
+        match slot.unlock(password) {
+            Ok(master_key) => return Ok((slot.user.clone(), master_key)),
+            Err(ClogError::WrongPassword) => continue,
+            Err(err) => return Err(err),
+        }
+    }
+    Err(ClogError::WrongPassword)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::backend::user::utils::generate_keys::generate_base64_key;
+
+    #[test]
+    fn slots_only_open_with_their_own_password() {
+        let master_key = generate_base64_key();
+        let slots = [
+            KeySlot::new("alice", "alice pw", Kdf::default(), &master_key).unwrap(),
+            KeySlot::new("bob", "bob pw", LEGACY_KDF, &master_key).unwrap(),
+        ];
+
+        // alice's slot uses the default every new clogfile gets, not a cheap test setting
+        let (user, key) = unlock_master_key(&slots, "alice pw").unwrap();
+        assert_eq!(
+            (user.as_str(), key.as_str()),
+            ("alice", master_key.as_str())
+        );
+        let (user, key) = unlock_master_key(&slots, "bob pw").unwrap();
+        assert_eq!((user.as_str(), key.as_str()), ("bob", master_key.as_str()));
+        assert!(matches!(
+            unlock_master_key(&slots, "eve pw"),
+            Err(ClogError::WrongPassword)
+        ));
+        assert!(matches!(
+            KeySlot::new(
+                &"x".repeat(MAX_USER_NAME_LENGTH + 1),
+                "pw",
+                Kdf::default(),
+                &master_key
+            ),
+            Err(ClogError::InvalidUser(_))
+        ));
+    }
+}
@@ -1 +1,2 @@
+pub mod key_slot;
 pub mod utils;
@@ -1,18 +1,181 @@
+use crate::backend::error::{ClogError, Result};
+use argon2::{Algorithm, Argon2, Version};
 use base64::{Engine, engine};
 use hmac::Hmac;
 use pbkdf2::pbkdf2;
 use sha2::Sha256;
 
-pub fn derive_key_base64(password: &str, base64_salt: &str) -> String {
-    let salt = engine::general_purpose::STANDARD
-        .decode(base64_salt)
-        .unwrap();
-    const ITERATIONS: u32 = 100_000;
-    const KEY_LENGTH: usize = 32;
+const KEY_LENGTH: usize = 32;
 
-    let mut derived_key = [0u8; KEY_LENGTH];
+/// most memory a key derivation may take, 16 times the default Argon2id cost; anything
+/// above comes from a damaged header rather than a deliberate choice
+const MAX_MEMORY_KIB: u64 = 1024 * 1024;
 
-    pbkdf2::<Hmac<Sha256>>(password.as_bytes(), &salt, ITERATIONS, &mut derived_key).unwrap();
+/// most passes, lanes or PBKDF2 iterations a key derivation may take
+const MAX_ARGON2_ITERATIONS: u32 = 64;
+const MAX_ARGON2_PARALLELISM: u32 = 64;
+const MAX_PBKDF2_ITERATIONS: u32 = 10_000_000;
+const MAX_SCRYPT_LOG_N: u8 = 24;
+const MAX_SCRYPT_PARALLELISM: u32 = 64;
 
-    engine::general_purpose::STANDARD.encode(derived_key)
+/// How a password is stretched into a key, along with its cost parameters.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kdf {
+    /// PBKDF2-HMAC-SHA256, what every clogfile before v4 used
+    Pbkdf2 { iterations: u32 },
+    /// scrypt with `N = 2^log_n`
+    Scrypt { log_n: u8, r: u32, p: u32 },
+    /// Argon2id, memory in KiB
+    Argon2id {
+        memory_kib: u32,
+        iterations: u32,
+        parallelism: u32,
+    },
+}
+
+/// the KDF of v1 to v3 clogfiles
+pub const LEGACY_KDF: Kdf = Kdf::Pbkdf2 {
+    iterations: 100_000,
+};
+
+impl Default for Kdf {
+    /// Argon2id with 64 MiB, 3 passes and 4 lanes (RFC 9106)
+    fn default() -> Self {
+        Kdf::Argon2id {
+            memory_kib: 64 * 1024,
+            iterations: 3,
+            parallelism: 4,
+        }
+    }
+}
+
+impl Kdf {
+    /// Fails with [`ClogError::InvalidKdf`] if deriving a key would take more memory or
+    /// time than any sane setting, so a damaged header can't exhaust the machine.
+    pub fn check_cost(&self) -> Result<()> {
+        let within_limits = match *self {
+            Kdf::Pbkdf2 { iterations } => iterations <= MAX_PBKDF2_ITERATIONS,
+            // scrypt takes 128 * r * N bytes
+            Kdf::Scrypt { log_n, r, p } => {
+                log_n <= MAX_SCRYPT_LOG_N
+                    && p <= MAX_SCRYPT_PARALLELISM
+                    && (r as u64).saturating_mul(128 << log_n) <= MAX_MEMORY_KIB * 1024
+            }
+            Kdf::Argon2id {
+                memory_kib,
+                iterations,
+                parallelism,
+            } => {
+                memory_kib as u64 <= MAX_MEMORY_KIB
+                    && iterations <= MAX_ARGON2_ITERATIONS
+                    && parallelism <= MAX_ARGON2_PARALLELISM
+            }
+        };
+
+        match within_limits {
+            true => Ok(()),
+            false => Err(ClogError::InvalidKdf(format!("{:?} costs too much", self))),
+        }
+    }
+
+    /// derives a 32 byte key from `password` and `base64_salt`, base64 encoded
+    pub fn derive_key_base64(&self, password: &str, base64_salt: &str) -> Result<String> {
+        self.check_cost()?;
+        let salt = engine::general_purpose::STANDARD
+            .decode(base64_salt)
+            .map_err(|_| ClogError::CorruptHeader("salt is not valid base64".to_string()))?;
+
+        let mut derived_key = [0u8; KEY_LENGTH];
+        let password = password.as_bytes();
+
+        match *self {
+            Kdf::Pbkdf2 { iterations } => {
+                pbkdf2::<Hmac<Sha256>>(password, &salt, iterations, &mut derived_key)
+                    .map_err(|_| ClogError::InvalidKdf("could not derive key".to_string()))?;
+            }
+            Kdf::Scrypt { log_n, r, p } => {
+                let params = scrypt::Params::new(log_n, r, p, KEY_LENGTH)
+                    .map_err(|err| ClogError::InvalidKdf(format!("scrypt: {}", err)))?;
+                scrypt::scrypt(password, &salt, &params, &mut derived_key)
+                    .map_err(|err| ClogError::InvalidKdf(format!("scrypt: {}", err)))?;
+            }
+            Kdf::Argon2id {
+                memory_kib,
+                iterations,
+                parallelism,
+            } => {
+                let params =
+                    argon2::Params::new(memory_kib, iterations, parallelism, Some(KEY_LENGTH))
+                        .map_err(|err| ClogError::InvalidKdf(format!("argon2id: {}", err)))?;
+                Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+                    .hash_password_into(password, &salt, &mut derived_key)
+                    .map_err(|err| ClogError::InvalidKdf(format!("argon2id: {}", err)))?;
+            }
+        }
+
+        Ok(engine::general_purpose::STANDARD.encode(derived_key))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::backend::user::utils::generate_keys::generate_base64_salt;
+
+    #[test]
+    fn every_kdf_is_deterministic_and_distinct() {
+        let salt = generate_base64_salt();
+        let kdfs = [
+            Kdf::Pbkdf2 { iterations: 1_000 },
+            Kdf::Scrypt {
+                log_n: 4,
+                r: 8,
+                p: 1,
+            },
+            Kdf::default(),
+        ];
+
+        let keys: Vec<String> = kdfs
+            .iter()
+            .map(|kdf| kdf.derive_key_base64("pw", &salt).unwrap())
+            .collect();
+        for (kdf, key) in kdfs.iter().zip(&keys) {
+            assert_eq!(&kdf.derive_key_base64("pw", &salt).unwrap(), key);
+            assert_ne!(&kdf.derive_key_base64("other", &salt).unwrap(), key);
+        }
+        assert_ne!(keys[0], keys[1]);
+        assert_ne!(keys[1], keys[2]);
+
+        let broken = Kdf::Argon2id {
+            memory_kib: 0,
+            iterations: 0,
+            parallelism: 0,
+        };
+        assert!(matches!(
+            broken.derive_key_base64("pw", &salt),
+            Err(ClogError::InvalidKdf(_))
+        ));
+
+        let greedy = [
+            Kdf::Pbkdf2 {
+                iterations: u32::MAX,
+            },
+            Kdf::Scrypt {
+                log_n: 30,
+                r: 8,
+                p: 1,
+            },
+            Kdf::Argon2id {
+                memory_kib: 16 * 1024 * 1024,
+                iterations: 3,
+                parallelism: 4,
+            },
+        ];
+        for kdf in greedy {
+            assert!(matches!(
+                kdf.derive_key_base64("pw", &salt),
+                Err(ClogError::InvalidKdf(_))
+            ));
+        }
+    }
 }
@@ -0,0 +1,465 @@
+pub use super::metadata::file::BlobId;
+use super::{
+    error::{ClogError, Result},
+    file_operations::{
+        content::{decrypt_bytes, read_exact_or_corrupt},
+        lock::{LockPolicy, lock_shared},
+        utils::open_file_read,
+    },
+    header::utils::{parse_header_from_bytes, parse_header_from_file},
+    metadata::metadata::Metadata,
+    user::key_slot::unlock_master_key,
+};
+use std::{
+    fmt, fs,
+    io::{Seek, SeekFrom},
+    path::PathBuf,
+};
+
+/// What a range of bytes in the clogfile holds.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Region {
+    Header,
+    /// the metadata record the header points at, with the header snapshot before it
+    Metadata,
+    /// one ciphertext of an entry
+    Entry {
+        folder: String,
+        file: String,
+        blob: BlobId,
+    },
+}
+
+/// One thing [`verify`] found wrong with a clogfile.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Problem {
+    /// The header can't be parsed, nothing past it was checked.
+    Header(String),
+    /// The metadata record the header points at isn't inside the file, no entry was
+    /// checked.
+    MetadataOutOfBounds {
+        offset: usize,
+        length: usize,
+        file_length: u64,
+    },
+    /// The header snapshot before the metadata record is damaged, the clogfile reads fine
+    /// but can't be recovered from it.
+    Snapshot,
+    /// The metadata record can't be decrypted or decoded, no entry was checked.
+    Metadata(String),
+    /// A ciphertext of an entry isn't inside the file, or starts inside the header.
+    OutOfBounds {
+        region: Region,
+        offset: usize,
+        length: usize,
+    },
+    /// Two regions claim the same bytes.
+    Overlap { first: Region, second: Region },
+    /// A ciphertext of an entry fails authentication.
+    CorruptEntry(Region),
+}
+
+/// What [`verify`] found in a clogfile.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct VerifyReport {
+    /// format version from the header, if it could be parsed
+    pub version: Option<u16>,
+    /// how many ciphertexts of entries were authenticated
+    pub blobs_checked: usize,
+    /// everything found wrong, empty for a healthy clogfile
+    pub problems: Vec<Problem>,
+    /// (offset,length) of every byte range nothing points at. These are the dead bytes
+    /// every write leaves behind and `compact` frees, not damage.
+    pub orphaned: Vec<(usize, usize)>,
+}
+
+impl VerifyReport {
+    /// whether no problem was found, orphaned bytes don't count
+    pub fn is_healthy(&self) -> bool {
+        self.problems.is_empty()
+    }
+
+    /// how many bytes nothing points at
+    pub fn orphaned_bytes(&self) -> usize {
+        self.orphaned.iter().map(|&(_, length)| length).sum()
+    }
+}
+
+/// Checks a clogfile without changing it, reporting every problem found instead of
+/// stopping at the first one.
+///
+/// Fails only if the file can't be read or `password` opens none of the key slots,
+/// which can't be told apart from a damaged key slot. The single slot of v1 and v2
+/// clogfiles takes any password, there an otherwise sound metadata record failing
+/// authentication means the password is wrong.
+pub fn verify(clogfile_path: &PathBuf, password: &str) -> Result<VerifyReport> {
+    let _lock = lock_shared(clogfile_path, LockPolicy::default())?;
+    let file_length = fs::metadata(clogfile_path)?.len();
+    let mut report = VerifyReport::default();
+    let in_file = |offset: usize, length: usize| {
+        offset
+            .checked_add(length)
+            .is_some_and(|end| end as u64 <= file_length)
+    };
+
+    // 1. Parse the header
+    let header = match parse_header_from_file(clogfile_path) {
+        Ok(header) => header,
+        Err(ClogError::Io(err)) => return Err(ClogError::Io(err)),
+        Err(err) => {
+            report.problems.push(Problem::Header(err.to_string()));
+            return Ok(report);
+        }
+    };
+    report.version = Some(header.version);
+    let header_length = header.length();
+
+    // 2. Check the metadata record and its header snapshot lie inside the file
+    let metadata_offset = header
+        .metadata_offset
+        .saturating_sub(header.snapshot_length());
+    let metadata_length = header
+        .metadata_length
+        .saturating_add(header.snapshot_length());
+    if header.metadata_offset < header.snapshot_length()
+        || !in_file(metadata_offset, metadata_length)
+        || header_length.is_some_and(|header_length| metadata_offset < header_length)
+    {
+        report.problems.push(Problem::MetadataOutOfBounds {
+            offset: metadata_offset,
+            length: metadata_length,
+            file_length,
+        });
+        return Ok(report);
+    }
+
+    // the snapshot must point at the record it precedes, or a damaged header can't be
+    // recovered from it
+    if header.snapshot_length() > 0 {
+        let mut file = open_file_read(clogfile_path)?;
+        file.seek(SeekFrom::Start(metadata_offset as u64))?;
+        let mut snapshot = vec![0u8; header.snapshot_length()];
+        read_exact_or_corrupt(&mut file, &mut snapshot, "header snapshot")?;
+        let points_at_record = parse_header_from_bytes(&snapshot).is_ok_and(|snapshot| {
+            (snapshot.metadata_offset, snapshot.metadata_length)
+                == (header.metadata_offset, header.metadata_length)
+        });
+        if !points_at_record {
+            report.problems.push(Problem::Snapshot);
+        }
+    }
+
+    // 3. Decrypt the metadata
+    let (_, base64_key) = unlock_master_key(&header.key_slots, password)?;
+    let metadata = match Metadata::extract_metadata_with_key(clogfile_path, &base64_key) {
+        Ok(metadata) => metadata,
+        Err(ClogError::Io(err)) => return Err(ClogError::Io(err)),
+        // a legacy slot takes any password, the metadata is the first thing to check it
+        Err(ClogError::WrongPassword)
+            if report.problems.is_empty()
+                && header.key_slots.iter().any(|slot| slot.is_legacy()) =>
+        {
+            return Err(ClogError::WrongPassword);
+        }
+        // the key slot opened, so the metadata itself is damaged
+        Err(ClogError::WrongPassword) => {
+            report.problems.push(Problem::Metadata(
+                "metadata fails authentication".to_string(),
+            ));
+            return Ok(report);
+        }
+        Err(err) => {
+            report.problems.push(Problem::Metadata(err.to_string()));
+            return Ok(report);
+        }
+    };
+
+    // 4. Authenticate every ciphertext of every entry
+    let mut regions = vec![(metadata_offset, metadata_length, Region::Metadata)];
+    let mut file = open_file_read(clogfile_path)?;
+    for (foldername, folder) in metadata.all_folders() {
+        for (filename, entry) in &folder.files {
+            for blob in entry.blobs() {
+                let Some((base64_key, base64_nonce, offset, length)) = entry.blob_parameters(blob)
+                else {
+                    continue;
+                };
+                let region = Region::Entry {
+                    folder: foldername.clone(),
+                    file: filename.clone(),
+                    blob,
+                };
+
+                if !in_file(offset, length)
+                    || header_length.is_some_and(|header_length| offset < header_length)
+                {
+                    report.problems.push(Problem::OutOfBounds {
+                        region,
+                        offset,
+                        length,
+                    });
+                    continue;
+                }
+
+                file.seek(SeekFrom::Start(offset as u64))?;
+                let mut stored = vec![0u8; length];
+                read_exact_or_corrupt(&mut file, &mut stored, "file content")?;
+                let authentic = header
+                    .decode_blob(stored)
+                    .and_then(|ciphertext| decrypt_bytes(&ciphertext, base64_key, base64_nonce))
+                    .is_ok();
+
+                report.blobs_checked += 1;
+                if !authentic {
+                    report.problems.push(Problem::CorruptEntry(region.clone()));
+                }
+                regions.push((offset, length, region));
+            }
+        }
+    }
+
+    // 5. Look for overlapping and orphaned ranges, the v1 header ends where its data starts
+    let data_start = header_length
+        .or_else(|| regions.iter().map(|&(offset, _, _)| offset).min())
+        .unwrap_or(0);
+    regions.push((0, data_start, Region::Header));
+    regions.sort_by_key(|&(offset, length, _)| (offset, length));
+
+    let mut covered_end = 0;
+    let mut covered_by = &Region::Header;
+    for (offset, length, region) in regions.iter().filter(|(_, length, _)| *length > 0) {
+        if *offset < covered_end {
+            report.problems.push(Problem::Overlap {
+                first: covered_by.clone(),
+                second: region.clone(),
+            });
+        } else if *offset > covered_end {
+            report.orphaned.push((covered_end, offset - covered_end));
+        }
+
+        if offset + length > covered_end {
+            covered_end = offset + length;
+            covered_by = region;
+        }
+    }
+    if file_length > covered_end as u64 {
+        report
+            .orphaned
+            .push((covered_end, file_length as usize - covered_end));
+    }
+
+    Ok(report)
+}
+
+impl fmt::Display for Region {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Region::Header => write!(f, "header"),
+            Region::Metadata => write!(f, "metadata"),
+            Region::Entry { folder, file, blob } => {
+                write!(f, "file '{}' in folder '{}'", file, folder)?;
+                if let Some(version) = blob.version {
+                    write!(f, " version {}", version)?;
+                }
+                if let Some(chunk) = blob.chunk {
+                    write!(f, " chunk {}", chunk)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Problem::Header(reason) => write!(f, "unreadable header: {}", reason),
+            Problem::MetadataOutOfBounds {
+                offset,
+                length,
+                file_length,
+            } => write!(
+                f,
+                "metadata at {}..{} is outside the {} byte file",
+                offset,
+                offset.saturating_add(*length),
+                file_length
+            ),
+            Problem::Snapshot => write!(f, "the header snapshot of the metadata is damaged"),
+            Problem::Metadata(reason) => write!(f, "unreadable metadata: {}", reason),
+            Problem::OutOfBounds {
+                region,
+                offset,
+                length,
+            } => write!(
+                f,
+                "{} at {}..{} is outside the data",
+                region,
+                offset,
+                offset.saturating_add(*length)
+            ),
+            Problem::Overlap { first, second } => write!(f, "{} overlaps {}", second, first),
+            Problem::CorruptEntry(region) => write!(f, "{} fails authentication", region),
+        }
+    }
+}
+
+impl fmt::Display for VerifyReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.version {
+            Some(version) => write!(f, "clog v{}", version)?,
+            None => write!(f, "clog of unknown version")?,
+        }
+        write!(
+            f,
+            ", {} ciphertexts checked, {} orphaned bytes in {} ranges",
+            self.blobs_checked,
+            self.orphaned_bytes(),
+            self.orphaned.len()
+        )?;
+
+        match self.is_healthy() {
+            true => write!(f, ", healthy"),
+            false => {
+                write!(f, ", {} problems", self.problems.len())?;
+                for problem in &self.problems {
+                    write!(f, "\n- {}", problem)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::backend::{
+        clock::FixedClock,
+        file_operations::utils::make_new_clogfile,
+        header::utils::write_metadata_pointer,
+        session::Clog,
+        test_utils::{TEST_KDF, make_v1_clogfile, temp_clogfile},
+    };
+    use std::sync::Arc;
+
+    fn flip_byte(path: &PathBuf, at: usize) {
+        let mut bytes = fs::read(path).unwrap();
+        bytes[at] ^= 0xff;
+        fs::write(path, bytes).unwrap();
+    }
+
+    #[test]
+    fn verify_reports_damage_and_counts_dead_bytes_as_orphaned() {
+        let path = temp_clogfile("verify");
+        let clock = Arc::new(FixedClock::new(
+            chrono::DateTime::parse_from_rfc3339("2025-05-25T10:00:00+02:00").unwrap(),
+        ));
+        make_new_clogfile("pw", &path, TEST_KDF, &*clock).unwrap();
+        let mut clog = Clog::open(&path, "pw").unwrap().with_clock(clock.clone());
+        clog.set_version_limit(2).unwrap();
+        clog.add_file("a", "first").unwrap();
+        clog.edit_file("25/05/2025", "a", "first, edited").unwrap();
+        clog.append_to_file("25/05/2025", "a", " and appended")
+            .unwrap();
+
+        // every byte a write left behind is orphaned, none of it is a problem
+        let report = verify(&path, "pw").unwrap();
+        assert!(report.is_healthy(), "{}", report);
+        assert_eq!(report.blobs_checked, 4);
+        assert_eq!(report.orphaned_bytes() as u64, clog.dead_bytes().unwrap());
+        assert!(matches!(
+            verify(&path, "not pw"),
+            Err(ClogError::WrongPassword)
+        ));
+
+        let offset = clog.metadata().get_file("25/05/2025", "a").unwrap().offset;
+        drop(clog);
+        flip_byte(&path, offset);
+        let report = verify(&path, "pw").unwrap();
+        assert_eq!(
+            report.problems,
+            [Problem::CorruptEntry(Region::Entry {
+                folder: "25/05/2025".to_string(),
+                file: "a".to_string(),
+                blob: BlobId {
+                    version: None,
+                    chunk: None
+                },
+            })]
+        );
+        flip_byte(&path, offset);
+
+        // pointing the header past the end leaves nothing else to check
+        let mut header = parse_header_from_file(&path).unwrap();
+        let file_length = fs::metadata(&path).unwrap().len();
+        header.metadata_offset = file_length as usize;
+        write_metadata_pointer(&path, &header).unwrap();
+        let report = verify(&path, "pw").unwrap();
+        assert!(matches!(
+            report.problems[..],
+            [Problem::MetadataOutOfBounds { file_length: length, .. }] if length == file_length
+        ));
+        assert_eq!(report.blobs_checked, 0);
+    }
+
+    #[test]
+    fn verify_reports_absurd_kdf_costs_instead_of_running_them() {
+        let path = temp_clogfile("verify_kdf");
+        make_new_clogfile("pw", &path, TEST_KDF, &crate::backend::clock::SystemClock).unwrap();
+
+        // the high byte of the argon2id memory cost of the first key slot
+        flip_byte(&path, 75);
+        let report = verify(&path, "pw").unwrap();
+        assert!(matches!(report.problems[..], [Problem::Header(_)]));
+        assert!(matches!(
+            Clog::open(&path, "pw"),
+            Err(ClogError::CorruptHeader(_))
+        ));
+    }
+
+    #[test]
+    fn verify_tells_a_wrong_password_on_legacy_files() {
+        let path = temp_clogfile("verify_v1");
+        make_v1_clogfile("pw", &path);
+
+        assert!(verify(&path, "pw").unwrap().is_healthy());
+        assert!(matches!(
+            verify(&path, "not pw"),
+            Err(ClogError::WrongPassword)
+        ));
+    }
+
+    #[test]
+    fn verify_reports_overlaps_and_unreadable_headers() {
+        let path = temp_clogfile("verify_overlap");
+        make_new_clogfile("pw", &path, TEST_KDF, &crate::backend::clock::SystemClock).unwrap();
+        let mut clog = Clog::open(&path, "pw").unwrap();
+        clog.add_file("a", "first").unwrap();
+        clog.add_file("b", "second").unwrap();
+        let today = clog.list().unwrap()[0].folder.clone();
+        drop(clog);
+
+        // b claims a's bytes
+        let (_, base64_key) = Metadata::unlock_key_from_file(&path, "pw").unwrap();
+        let mut metadata = Metadata::extract_metadata_with_key(&path, &base64_key).unwrap();
+        let a_offset = metadata.get_file(&today, "a").unwrap().offset;
+        metadata.get_file_mut(&today, "b").unwrap().offset = a_offset;
+        metadata
+            .update_metadata_in_file(&path, &base64_key)
+            .unwrap();
+
+        let report = verify(&path, "pw").unwrap();
+        assert!(report.problems.iter().any(|problem| matches!(
+            problem,
+            Problem::Overlap { second: Region::Entry { file, .. }, .. }
+                | Problem::Overlap { first: Region::Entry { file, .. }, .. } if file == "b"
+        )));
+        assert!(report.to_string().contains("overlaps"));
+
+        fs::write(&path, vec![0xab; 200]).unwrap();
+        let report = verify(&path, "pw").unwrap();
+        assert_eq!(report.version, None);
+        assert!(matches!(report.problems[..], [Problem::Header(_)]));
+    }
+}
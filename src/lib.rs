@@ -1,19 +1,67 @@
 use std::path::PathBuf;
 mod backend;
+pub use backend::error::{ClogError, Result};
 use backend::main;
-use chrono::Local;
+pub use backend::recover::RecoveryReport;
+pub use backend::session::{
+    AuditAction, AuditRecord, Clock, Clog, ClogPath, EntryInfo, FixedClock, FolderKind,
+    FolderListing, Kdf, LockPolicy, RolloverReport, SystemClock, VersionInfo,
+};
+pub use backend::verify::{BlobId, Problem, Region, VerifyReport};
 
-/// Daily check and update
-fn daily_check_and_update_metadata(clogfile_path: &str, password: &str) {
+/// Seals every past folder and creates today's folder, reporting what changed
+pub fn roll_over(password: &str, clogfile_path: &str) -> Result<RolloverReport> {
     let path = PathBuf::from(clogfile_path);
-    main::daily_check_and_update_metadata(&path, password);
+    main::daily_check_and_update_metadata(&path, password)
 }
 
 /// Adds a new user
-pub fn add_new_user(password: &str, clogfile_path: &str) {
+pub fn add_new_user(password: &str, clogfile_path: &str) -> Result<()> {
     let path = PathBuf::from(clogfile_path);
-    main::add_new_user(&path, password);
-    daily_check_and_update_metadata(clogfile_path, password);
+    main::add_new_user(&path, password)?;
+    roll_over(password, clogfile_path)?;
+    Ok(())
+}
+
+/// Checks the password, Ok(false) means wrong password while Err means the clogfile is damaged
+pub fn verify_password(password: &str, clogfile_path: &str) -> Result<bool> {
+    let path = PathBuf::from(clogfile_path);
+    main::verify_password(&path, password)
+}
+
+/// Changes the password of the user `old_password` belongs to
+pub fn change_password(old_password: &str, clogfile_path: &str, new_password: &str) -> Result<()> {
+    let path = PathBuf::from(clogfile_path);
+    main::change_password(&path, old_password, new_password)
+}
+
+/// Re-derives the key slot `password` belongs to with `kdf`, e.g. to move from PBKDF2 to Argon2id
+pub fn rekey_kdf(password: &str, clogfile_path: &str, kdf: Kdf) -> Result<()> {
+    let path = PathBuf::from(clogfile_path);
+    main::rekey_kdf(&path, password, kdf)
+}
+
+/// Gives another user their own password, `password` must belong to an existing user
+pub fn add_user(
+    password: &str,
+    clogfile_path: &str,
+    new_user: &str,
+    new_password: &str,
+) -> Result<()> {
+    let path = PathBuf::from(clogfile_path);
+    main::add_user(&path, password, new_user, new_password)
+}
+
+/// Removes a user, `password` must belong to an existing user
+pub fn remove_user(password: &str, clogfile_path: &str, user: &str) -> Result<()> {
+    let path = PathBuf::from(clogfile_path);
+    main::remove_user(&path, password, user)
+}
+
+/// Lists every user that can open the clogfile
+pub fn list_users(clogfile_path: &str) -> Result<Vec<String>> {
+    let path = PathBuf::from(clogfile_path);
+    main::list_users(&path)
 }
 
 /// Edits a file
@@ -23,9 +71,131 @@ pub fn update_file_content(
     filename: &str,
     foldername: &str,
     new_file_content: &str,
-) {
+) -> Result<()> {
+    let path = PathBuf::from(clogfile_path);
+    main::edit_file(password, &path, filename, foldername, new_file_content)
+}
+
+/// Adds text to the end of a file without rewriting its content or anything stored after it
+pub fn append_to_file(
+    password: &str,
+    clogfile_path: &str,
+    filename: &str,
+    foldername: &str,
+    text: &str,
+) -> Result<()> {
+    let path = PathBuf::from(clogfile_path);
+    main::append_to_file(password, &path, filename, foldername, text)
+}
+
+/// Edits a file in a read-only folder, recording `reason` in the audit log
+pub fn override_file_content(
+    password: &str,
+    clogfile_path: &str,
+    filename: &str,
+    foldername: &str,
+    new_file_content: &str,
+    reason: &str,
+) -> Result<()> {
+    let path = PathBuf::from(clogfile_path);
+    main::override_file_content(
+        password,
+        &path,
+        filename,
+        foldername,
+        new_file_content,
+        reason,
+    )
+}
+
+/// Deletes a file, with `wipe` every old copy of it is overwritten with random bytes too
+pub fn delete_file(
+    password: &str,
+    clogfile_path: &str,
+    filename: &str,
+    foldername: &str,
+    wipe: bool,
+) -> Result<()> {
     let path = PathBuf::from(clogfile_path);
-    main::edit_file(password, &path, filename, foldername, new_file_content);
+    main::delete_file(password, &path, filename, foldername, wipe)
+}
+
+/// Deletes a file in a read-only folder, recording `reason` in the audit log
+pub fn override_delete_file(
+    password: &str,
+    clogfile_path: &str,
+    filename: &str,
+    foldername: &str,
+    wipe: bool,
+    reason: &str,
+) -> Result<()> {
+    let path = PathBuf::from(clogfile_path);
+    main::override_delete_file(password, &path, filename, foldername, wipe, reason)
+}
+
+/// Renames a file within its folder without touching its content
+pub fn rename_file(
+    password: &str,
+    clogfile_path: &str,
+    filename: &str,
+    foldername: &str,
+    new_filename: &str,
+) -> Result<()> {
+    let path = PathBuf::from(clogfile_path);
+    main::rename_file(password, &path, filename, foldername, new_filename)
+}
+
+/// Moves a file to another existing folder without touching its content
+pub fn move_file(
+    password: &str,
+    clogfile_path: &str,
+    filename: &str,
+    foldername: &str,
+    new_foldername: &str,
+) -> Result<()> {
+    let path = PathBuf::from(clogfile_path);
+    main::move_file(password, &path, filename, foldername, new_foldername)
+}
+
+/// Keeps up to `limit` earlier contents of every entry when it is edited, 0 turns the history off
+pub fn set_version_limit(password: &str, clogfile_path: &str, limit: usize) -> Result<()> {
+    let path = PathBuf::from(clogfile_path);
+    main::set_version_limit(password, &path, limit)
+}
+
+/// Lists the kept contents of a file, oldest first and ending with the current one
+pub fn list_versions(
+    password: &str,
+    clogfile_path: &str,
+    filename: &str,
+    foldername: &str,
+) -> Result<Vec<VersionInfo>> {
+    let path = PathBuf::from(clogfile_path);
+    main::list_versions(password, &path, filename, foldername)
+}
+
+/// Decrypts the content of a file numbered `version`
+pub fn get_version(
+    password: &str,
+    clogfile_path: &str,
+    filename: &str,
+    foldername: &str,
+    version: u64,
+) -> Result<String> {
+    let path = PathBuf::from(clogfile_path);
+    main::get_version(password, &path, filename, foldername, version)
+}
+
+/// Makes the content numbered `version` current again, like an edit
+pub fn restore_version(
+    password: &str,
+    clogfile_path: &str,
+    filename: &str,
+    foldername: &str,
+    version: u64,
+) -> Result<()> {
+    let path = PathBuf::from(clogfile_path);
+    main::restore_version(password, &path, filename, foldername, version)
 }
 
 /// Decrypt file
@@ -34,22 +204,90 @@ pub fn get_file_content(
     clogfile_path: &str,
     filename: &str,
     foldername: &str,
-) -> String {
+) -> Result<String> {
     let path = PathBuf::from(clogfile_path);
     main::get_file_content(&path, filename, foldername, password)
 }
 
-/// Adds a file
-pub fn add_file(password: &str, clogfile_path: &str, filename: &str, file_content: &str) {
-    let foldername = Local::now().format("%d/%m/%Y").to_string();
+/// Adds a file to today's folder
+pub fn add_file(
+    password: &str,
+    clogfile_path: &str,
+    filename: &str,
+    file_content: &str,
+) -> Result<()> {
+    let path = PathBuf::from(clogfile_path);
+    main::add_file(password, &path, filename, file_content)
+}
+
+/// Add a new file to an existing folder, e.g. `projects/clog/ideas`
+pub fn add_file_to(
+    password: &str,
+    clogfile_path: &str,
+    filename: &str,
+    foldername: &str,
+    file_content: &str,
+) -> Result<()> {
+    let path = PathBuf::from(clogfile_path);
+    main::add_file_to(password, &path, filename, foldername, file_content)
+}
+
+/// Creates an empty folder inside an existing one
+pub fn mkdir(password: &str, clogfile_path: &str, folder_path: &str) -> Result<()> {
+    let path = PathBuf::from(clogfile_path);
+    main::mkdir(password, &path, folder_path)
+}
+
+/// Removes an empty folder
+pub fn rmdir(password: &str, clogfile_path: &str, folder_path: &str) -> Result<()> {
+    let path = PathBuf::from(clogfile_path);
+    main::rmdir(password, &path, folder_path)
+}
+
+/// Seals a folder so it can only be changed with an audited override
+pub fn seal_folder(password: &str, clogfile_path: &str, folder_path: &str) -> Result<()> {
+    let path = PathBuf::from(clogfile_path);
+    main::seal_folder(password, &path, folder_path)
+}
+
+/// Lists the folders and files directly inside a folder, `""` for the top-level folders
+pub fn ls(password: &str, clogfile_path: &str, folder_path: &str) -> Result<FolderListing> {
+    let path = PathBuf::from(clogfile_path);
+    main::ls(password, &path, folder_path)
+}
+
+/// Rewrites the clogfile without stale bytes, returns how many bytes were freed
+pub fn compact(password: &str, clogfile_path: &str) -> Result<u64> {
+    let path = PathBuf::from(clogfile_path);
+    main::compact(password, &path)
+}
+
+/// Counts the bytes left behind by earlier writes, which `compact` would free
+pub fn dead_bytes(password: &str, clogfile_path: &str) -> Result<u64> {
+    let path = PathBuf::from(clogfile_path);
+    main::dead_bytes(password, &path)
+}
+
+/// Checks the clogfile for damage without changing it
+pub fn verify(password: &str, clogfile_path: &str) -> Result<VerifyReport> {
+    let path = PathBuf::from(clogfile_path);
+    main::verify(password, &path)
+}
+
+/// Salvages whatever can still be read of a damaged clogfile into a new one at `recovered_path`
+pub fn recover(
+    password: &str,
+    clogfile_path: &str,
+    recovered_path: &str,
+) -> Result<RecoveryReport> {
     let path = PathBuf::from(clogfile_path);
-    daily_check_and_update_metadata(clogfile_path, password);
-    main::add_file(password, &path, filename, &foldername, file_content);
+    let recovered_path = PathBuf::from(recovered_path);
+    main::recover(password, &path, &recovered_path)
 }
 
 /// Get Metadata in json_serialized
-pub fn get_json_metadata(password: &str, clogfile_path: &str) -> String {
-    daily_check_and_update_metadata(clogfile_path, password);
+pub fn get_json_metadata(password: &str, clogfile_path: &str) -> Result<String> {
+    roll_over(password, clogfile_path)?;
     let path = PathBuf::from(clogfile_path);
     main::get_clean_metadata(password, &path)
 }